@@ -0,0 +1,86 @@
+//! Simple strategies for computer players.
+
+use libcoinche::{bid, cards, game, points, pos, trick};
+
+const SUITS: [cards::Suit; 4] = [cards::Suit::Heart,
+                                 cards::Suit::Spade,
+                                 cards::Suit::Diamond,
+                                 cards::Suit::Club];
+
+/// Minimum hand value (see `hand_value`) to open the auction.
+const OPENING_VALUE: i32 = 70;
+
+/// Estimates how good a hand is with the given trump.
+fn hand_value(hand: cards::Hand, trump: cards::Suit) -> i32 {
+    hand.list().into_iter().fold(0, |value, card| {
+        if card.suit() == trump {
+            // Trumps are worth more than their points: they win tricks.
+            value + points::score(card, trump) + 10
+        } else if card.rank() == cards::Rank::RankA {
+            value + points::score(card, trump)
+        } else {
+            value
+        }
+    })
+}
+
+/// Decides what to bid during the auction. Returns `None` to pass.
+///
+/// `opened` tells if someone already bid in this auction.
+/// This only opens the auction with a good hand, and never overbids.
+pub fn choose_bid(hand: cards::Hand, opened: bool) -> Option<(cards::Suit, bid::Target)> {
+    if opened {
+        return None;
+    }
+
+    let (value, suit) = SUITS.iter()
+                             .map(|&suit| (hand_value(hand, suit), suit))
+                             .max_by_key(|&(value, _)| value)
+                             .unwrap();
+
+    if value >= OPENING_VALUE {
+        Some((suit, bid::Target::Contract80))
+    } else {
+        None
+    }
+}
+
+/// Picks a card to play. The card is always legal.
+pub fn choose_card(pos: pos::PlayerPos,
+                   hand: cards::Hand,
+                   trick: &trick::Trick,
+                   trump: cards::Suit)
+                   -> cards::Card {
+    let playable: Vec<cards::Card> = hand.list()
+                                         .into_iter()
+                                         .filter(|&card| {
+                                             game::can_play(pos, card, hand, trick, trump).is_ok()
+                                         })
+                                         .collect();
+
+    // Cards which would win the trick, for now.
+    let winning: Vec<cards::Card> = playable.iter()
+                                            .cloned()
+                                            .filter(|&card| {
+                                                let mut trick = trick.clone();
+                                                trick.play_card(pos, card, trump);
+                                                trick.winner == pos
+                                            })
+                                            .collect();
+
+    let card = if trick.first == pos {
+        // We start: go for it.
+        playable.iter().cloned().max_by_key(|&card| points::strength(card, trump))
+    } else if trick.winner.team() == pos.team() {
+        // Partner is winning: give him some points.
+        playable.iter().cloned().max_by_key(|&card| points::score(card, trump))
+    } else if !winning.is_empty() {
+        // Take the trick, as cheaply as possible.
+        winning.iter().cloned().min_by_key(|&card| points::strength(card, trump))
+    } else {
+        // This one is lost: keep our good cards.
+        playable.iter().cloned().min_by_key(|&card| points::score(card, trump))
+    };
+
+    card.expect("no playable card")
+}
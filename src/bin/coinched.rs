@@ -1,39 +1,184 @@
 extern crate coinched;
 extern crate clap;
+extern crate chan_signal;
 extern crate env_logger;
 #[macro_use]
 extern crate log;
 
 use std::str::FromStr;
-use clap::{Arg, App};
+use std::thread;
+use clap::{Arg, App, ArgMatches};
+use chan_signal::Signal;
+use coinched::server::config::Config;
+
+/// Parses an optional number argument.
+///
+/// Exits on invalid input.
+fn read_number<T: FromStr>(matches: &ArgMatches, name: &str, what: &str) -> Option<T>
+    where T::Err: std::fmt::Display
+{
+    matches.value_of(name).map(|value| {
+        match T::from_str(value) {
+            Ok(n) => n,
+            Err(err) => {
+                println!("Invalid {}: `{}` ({})", what, value, err);
+                std::process::exit(1);
+            }
+        }
+    })
+}
+
+/// Reads the settings given on the command line.
+fn read_args(matches: &ArgMatches) -> Config {
+    let string = |name: &str| matches.value_of(name).map(|value| value.to_string());
+
+    Config {
+        port: read_number(matches, "PORT", "port"),
+        bind: matches.values_of("BIND")
+                     .map(|addresses| addresses.iter().map(|a| a.to_string()).collect()),
+        cert: string("CERT"),
+        key: string("KEY"),
+        admin_port: read_number(matches, "ADMIN_PORT", "admin port"),
+        admin_secret: string("ADMIN_SECRET"),
+        ws_port: read_number(matches, "WS_PORT", "WebSocket port"),
+        tcp_port: read_number(matches, "TCP_PORT", "TCP port"),
+        join_timeout: read_number(matches, "JOIN_TIMEOUT", "join timeout"),
+        wait_timeout: read_number(matches, "WAIT_TIMEOUT", "wait timeout"),
+        idle_timeout: read_number(matches, "IDLE_TIMEOUT", "idle timeout"),
+        spectator_delay: read_number(matches, "SPECTATOR_DELAY", "spectator delay"),
+        target_score: read_number(matches, "TARGET_SCORE", "target score"),
+        data_dir: string("DATA_DIR"),
+        log: string("LOG"),
+    }
+}
 
 fn main() {
-    env_logger::init().unwrap();
+    // Before any thread is started, so they all leave these signals to us.
+    let signals = chan_signal::notify(&[Signal::INT, Signal::TERM]);
 
     let matches = App::new("coinched")
                       .version(env!("CARGO_PKG_VERSION"))
                       .author("Alexandre Bury <alexandre.bury@gmail.com>")
                       .about("A coinche server")
+                      .arg(Arg::with_name("CONFIG")
+                               .help("JSON config file. Command-line options override it")
+                               .short("c")
+                               .long("config")
+                               .takes_value(true))
                       .arg(Arg::with_name("PORT")
                                .help("Port to listen to (defaults to 3000)")
                                .short("p")
                                .long("port")
                                .takes_value(true))
+                      .arg(Arg::with_name("BIND")
+                               .help("Address to listen to, like `0.0.0.0`, `::` or \
+                                      `[::1]:3000` (defaults to localhost, can be repeated)")
+                               .short("b")
+                               .long("bind")
+                               .takes_value(true)
+                               .multiple(true))
+                      .arg(Arg::with_name("CERT")
+                               .help("Certificate file, to serve HTTPS")
+                               .long("cert")
+                               .takes_value(true))
+                      .arg(Arg::with_name("KEY")
+                               .help("Private key file, to serve HTTPS")
+                               .long("key")
+                               .takes_value(true))
+                      .arg(Arg::with_name("ADMIN_PORT")
+                               .help("Port for the admin interface (disabled by default)")
+                               .long("admin-port")
+                               .takes_value(true))
+                      .arg(Arg::with_name("ADMIN_SECRET")
+                               .help("Token the admin interface expects, as a bearer token")
+                               .long("admin-secret")
+                               .takes_value(true))
+                      .arg(Arg::with_name("WS_PORT")
+                               .help("Port for the WebSocket interface (disabled by default)")
+                               .long("ws-port")
+                               .takes_value(true))
+                      .arg(Arg::with_name("TCP_PORT")
+                               .help("Port for the line-based JSON protocol over TCP (disabled \
+                                      by default)")
+                               .long("tcp-port")
+                               .takes_value(true))
+                      .arg(Arg::with_name("CONSOLE")
+                               .help("Read admin commands from stdin")
+                               .long("console"))
+                      .arg(Arg::with_name("JOIN_TIMEOUT")
+                               .help("Seconds before a join request gives up (defaults to 20)")
+                               .long("join-timeout")
+                               .takes_value(true))
+                      .arg(Arg::with_name("WAIT_TIMEOUT")
+                               .help("Seconds before a wait request gives up (defaults to 15)")
+                               .long("wait-timeout")
+                               .takes_value(true))
+                      .arg(Arg::with_name("IDLE_TIMEOUT")
+                               .help("Seconds of inactivity before a player is removed \
+                                      (defaults to 600)")
+                               .long("idle-timeout")
+                               .takes_value(true))
+                      .arg(Arg::with_name("TARGET_SCORE")
+                               .help("Score ending a party (by default, parties never end)")
+                               .long("target-score")
+                               .takes_value(true))
+                      .arg(Arg::with_name("DATA_DIR")
+                               .help("Directory where parties are saved, to resume them after a \
+                                      restart")
+                               .short("d")
+                               .long("data-dir")
+                               .takes_value(true))
+                      .arg(Arg::with_name("SPECTATOR_DELAY")
+                               .help("Seconds spectators are kept behind the players. With a \
+                                      delay, spectators also see every hand")
+                               .long("spectator-delay")
+                               .takes_value(true))
+                      .arg(Arg::with_name("LOG")
+                               .help("Log filter, like `info` or `coinched=trace` (defaults to \
+                                      RUST_LOG)")
+                               .long("log")
+                               .takes_value(true))
                       .get_matches();
 
-    let port = if let Some(port) = matches.value_of("PORT") {
-        match u16::from_str(port) {
-            Ok(port) => port,
-            Err(err) => {
-                println!("Invalid port: `{}` ({})", port, err);
-                std::process::exit(1);
+    let config = match matches.value_of("CONFIG") {
+        Some(path) => {
+            match Config::from_file(path) {
+                Ok(config) => config,
+                Err(err) => {
+                    println!("{}", err);
+                    std::process::exit(1);
+                }
             }
         }
-    } else {
-        3000
+        None => Config::default(),
     };
+    let config = config.merge(read_args(&matches));
+
+    match config.log {
+        Some(ref filter) => env_logger::LogBuilder::new().parse(filter).init().unwrap(),
+        None => env_logger::init().unwrap(),
+    }
+
+    let server = match coinched::server::http::Server::from_config(&config) {
+        Ok(server) => server,
+        Err(err) => {
+            println!("{}", err);
+            std::process::exit(1);
+        }
+    };
+
+    let manager = server.manager();
+    thread::spawn(move || {
+        if let Some(signal) = signals.recv() {
+            println!("Received {:?}, shutting down.", signal);
+            manager.shutdown("server shutting down");
+            std::process::exit(0);
+        }
+    });
 
-    let server = coinched::server::http::Server::new(port);
+    if matches.is_present("CONSOLE") {
+        coinched::server::console::spawn(server.manager());
+    }
 
     server.run();
 }
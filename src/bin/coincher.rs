@@ -4,16 +4,28 @@ extern crate libcoinche;
 extern crate clap;
 
 use std::io;
-use std::io::{BufRead, Write};
+use std::io::{BufRead, Read, Write};
+use std::fmt;
+use std::fs;
 use std::str::FromStr;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread;
 use libcoinche::{bid, cards, pos};
-use coinched::EventType;
+use coinched::{EventType, PlayerEvent, AccountBody, JoinBody, TableJoinBody};
 use coinched::client;
-use clap::{Arg, App};
+use coinched::server::game_manager::{GameManager, Settings};
+use coinched::replay::Replay;
+use clap::{Arg, App, SubCommand};
 
 struct CliFrontend {
     hand: cards::Hand,
     pos: pos::PlayerPos,
+    names: Vec<String>,
+
+    // In hot-seat mode, the terminal every player takes in turn.
+    hot_seat: Option<Arc<Mutex<()>>>,
+    // What happened since our last turn, when the terminal is someone else's.
+    log: Vec<String>,
 }
 
 fn parse_bid(line: &str) -> Result<(cards::Suit, bid::Target), String> {
@@ -28,14 +40,69 @@ fn parse_bid(line: &str) -> Result<(cards::Suit, bid::Target), String> {
     Ok((suit, target))
 }
 
+// Also clears the scrollback, so the next player can't scroll up to our hand.
+fn clear_screen() {
+    print!("\x1b[2J\x1b[3J\x1b[1;1H");
+    io::stdout().flush().unwrap();
+}
+
 impl CliFrontend {
-    fn new(pos: pos::PlayerPos) -> Self {
+    fn new(pos: pos::PlayerPos, names: Vec<String>) -> Self {
         CliFrontend {
             pos: pos,
             hand: cards::Hand::new(),
+            names: names,
+            hot_seat: None,
+            log: Vec::new(),
+        }
+    }
+
+    /// Creates a frontend sharing the terminal with other players.
+    ///
+    /// Everything is kept hidden until our turn, when the terminal is ours.
+    fn new_hot_seat(pos: pos::PlayerPos, names: Vec<String>, terminal: Arc<Mutex<()>>) -> Self {
+        let mut frontend = CliFrontend::new(pos, names);
+        frontend.hot_seat = Some(terminal);
+        frontend
+    }
+
+    // Shows a message now, or at our next turn in hot-seat mode.
+    fn say(&mut self, msg: String) {
+        if self.hot_seat.is_some() {
+            self.log.push(msg);
+        } else {
+            println!("{}", msg);
+        }
+    }
+
+    // Waits for the terminal, then shows what we missed.
+    fn begin_turn<'a>(&mut self, terminal: &'a Mutex<()>) -> MutexGuard<'a, ()> {
+        let turn = terminal.lock().unwrap();
+
+        clear_screen();
+        print!("{}'s turn. Press Enter when the others look away.", self.name(self.pos));
+        io::stdout().flush().unwrap();
+        Self::input();
+
+        for msg in self.log.drain(..) {
+            println!("{}", msg);
+        }
+        // The hand was not shown when it was dealt.
+        self.print_hand();
+        turn
+    }
+
+    // Hides our hand before the next player takes the terminal.
+    fn end_turn(&self) {
+        if self.hot_seat.is_some() {
+            clear_screen();
         }
     }
 
+    fn name(&self, pos: pos::PlayerPos) -> &str {
+        &self.names[pos as usize]
+    }
+
     fn input() -> String {
         let mut buffer = String::new();
         io::stdin().read_line(&mut buffer).unwrap();
@@ -58,33 +125,8 @@ impl CliFrontend {
         }
         println!("");
     }
-}
-
-impl client::Frontend<client::http::HttpBackend> for CliFrontend {
-    fn show_error(&mut self, error: client::http::Error) {
-        println!("Error: {:?}", error);
-    }
-
-    fn unexpected_event(&mut self, event: EventType) {
-        println!("Unexpected event: {:?}", event);
-    }
-
-    fn party_cancelled(&mut self, msg: &str) {
-        println!("Party cancelled: {}", msg);
-    }
 
-    fn show_card_played(&mut self, pos: pos::PlayerPos, card: cards::Card) {
-        println!("Player {:?} played {}", pos, card.to_string());
-        if pos == self.pos {
-            self.hand.remove(card);
-        }
-    }
-
-    fn show_trick_over(&mut self, winner: pos::PlayerPos) {
-        println!("{:?} gets the trick.", winner);
-    }
-
-    fn ask_card(&mut self) -> client::GameAction {
+    fn read_card(&mut self) -> client::GameAction {
         let cards = self.hand.list();
 
         loop {
@@ -105,30 +147,7 @@ impl client::Frontend<client::http::HttpBackend> for CliFrontend {
         }
     }
 
-    fn game_over(&mut self, points: [i32; 2], winner: pos::Team, scores: [i32; 2]) {
-        println!("Game over!");
-        println!("{:?} won. Points were {:?} ; scores: {:?}",
-                 winner,
-                 points,
-                 scores);
-    }
-
-    fn show_pass(&mut self, pos: pos::PlayerPos) {
-        println!("Player {:?} passed", pos);
-    }
-
-    fn show_coinche(&mut self, pos: pos::PlayerPos) {
-        println!("Player {:?} coinched", pos);
-    }
-
-    fn show_bid(&mut self, pos: pos::PlayerPos, suit: cards::Suit, target: bid::Target) {
-        println!("Player {:?} bid {} on {}",
-                 pos,
-                 target.to_string(),
-                 suit.to_string());
-    }
-
-    fn ask_bid(&mut self) -> client::AuctionAction {
+    fn read_bid(&mut self) -> client::AuctionAction {
         loop {
             println!("Your turn to bid. Commands:");
             println!("* `leave`");
@@ -160,24 +179,496 @@ impl client::Frontend<client::http::HttpBackend> for CliFrontend {
             };
         }
     }
+}
+
+impl<B: client::Backend> client::Frontend<B> for CliFrontend
+    where B::Error: fmt::Debug
+{
+    fn show_error(&mut self, error: B::Error) {
+        self.say(format!("Error: {:?}", error));
+    }
+
+    fn unexpected_event(&mut self, event: EventType) {
+        self.say(format!("Unexpected event: {:?}", event));
+    }
+
+    fn party_cancelled(&mut self, msg: &str) {
+        self.say(format!("Party cancelled: {}", msg));
+    }
+
+    fn show_card_played(&mut self, pos: pos::PlayerPos, card: cards::Card) {
+        let msg = format!("{} played {}", self.name(pos), card.to_string());
+        self.say(msg);
+        if pos == self.pos {
+            self.hand.remove(card);
+        }
+    }
+
+    fn show_trick_over(&mut self, winner: pos::PlayerPos) {
+        let msg = format!("{} gets the trick.", self.name(winner));
+        self.say(msg);
+    }
+
+    fn ask_card(&mut self) -> client::GameAction {
+        let terminal = self.hot_seat.clone();
+        let _turn = terminal.as_ref().map(|terminal| self.begin_turn(terminal));
+
+        let action = self.read_card();
+        self.end_turn();
+        action
+    }
+
+    fn game_over(&mut self, points: [i32; 2], winner: pos::Team, scores: [i32; 2]) {
+        self.say("Game over!".to_string());
+        self.say(format!("{:?} won. Points were {:?} ; scores: {:?}",
+                         winner,
+                         points,
+                         scores));
+    }
+
+    fn show_pass(&mut self, pos: pos::PlayerPos) {
+        let msg = format!("{} passed", self.name(pos));
+        self.say(msg);
+    }
+
+    fn show_coinche(&mut self, pos: pos::PlayerPos) {
+        let msg = format!("{} coinched", self.name(pos));
+        self.say(msg);
+    }
+
+    fn show_bid(&mut self, pos: pos::PlayerPos, suit: cards::Suit, target: bid::Target) {
+        let msg = format!("{} bid {} on {}",
+                          self.name(pos),
+                          target.to_string(),
+                          suit.to_string());
+        self.say(msg);
+    }
+
+    fn ask_bid(&mut self) -> client::AuctionAction {
+        let terminal = self.hot_seat.clone();
+        let _turn = terminal.as_ref().map(|terminal| self.begin_turn(terminal));
+
+        let action = self.read_bid();
+        self.end_turn();
+        action
+    }
 
     /// Auction cancelled, back to the start.
     fn auction_cancelled(&mut self) {
-        println!("Auction cancelled!");
+        self.say("Auction cancelled!".to_string());
     }
 
     /// Auction is complete, we can play now!
     fn auction_over(&mut self, contract: &bid::Contract) {
-        println!("Auction is over: {:?}", contract);
+        self.say(format!("Auction is over: {:?}", contract));
     }
 
     fn start_game(&mut self, first: pos::PlayerPos, hand: cards::Hand) {
         self.hand = hand;
 
-        self.print_hand();
+        // In hot-seat mode, the hand waits for our turn.
+        if self.hot_seat.is_none() {
+            self.print_hand();
+        }
 
 
-        println!("First player: {:?}", first);
+        let msg = format!("First player: {}", self.name(first));
+        self.say(msg);
+    }
+}
+
+/// Calls `join` again as long as no party is found.
+fn retry_join<F>(mut join: F) -> client::http::HttpBackend
+    where F: FnMut() -> Result<client::http::HttpBackend, client::http::Error>
+{
+    loop {
+        match join() {
+            Ok(backend) => return backend,
+            Err(ref err) if err.code() == Some("no_party_found") => {
+                println!("No party found yet, still waiting...");
+            }
+            Err(err) => panic!("Could not join: {:?}", err),
+        }
+    }
+}
+
+/// Parses an optional number argument, lower than `max`.
+///
+/// Exits on invalid input.
+fn read_number(matches: &clap::ArgMatches, name: &str, what: &str, max: u32) -> Option<u32> {
+    matches.value_of(name).map(|value| {
+        match u32::from_str(value) {
+            Ok(n) if n < max => n,
+            Ok(n) => {
+                println!("Invalid {}: `{}` (must be lower than {})", what, n, max);
+                std::process::exit(1);
+            }
+            Err(err) => {
+                println!("Invalid {}: `{}` ({})", what, value, err);
+                std::process::exit(1);
+            }
+        }
+    })
+}
+
+/// Logs in the account given with `--login`, registering it first if asked.
+///
+/// Returns the login token, or exits on failure.
+fn log_in(host: &str, matches: &clap::ArgMatches) -> Option<String> {
+    let name = match matches.value_of("LOGIN") {
+        Some(name) => name.to_string(),
+        None => return None,
+    };
+
+    print!("Password for {}: ", name);
+    io::stdout().flush().unwrap();
+    let mut password = String::new();
+    io::stdin().read_line(&mut password).unwrap();
+
+    let account = AccountBody {
+        name: name,
+        password: password.trim_right_matches(&['\r', '\n'][..]).to_string(),
+    };
+
+    if matches.is_present("REGISTER") {
+        if let Err(err) = client::http::HttpBackend::register(host, &account) {
+            println!("Could not register: {:?}", err);
+            std::process::exit(1);
+        }
+    }
+
+    match client::http::HttpBackend::login(host, &account) {
+        Ok(token) => Some(token),
+        Err(err) => {
+            println!("Could not log in: {:?}", err);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Joins a party, either in the public queue or at a private table.
+fn join(host: &str, matches: &clap::ArgMatches) -> client::http::HttpBackend {
+    let login = log_in(host, matches);
+    let login = login.as_ref().map(|token| &**token);
+
+    let table = if matches.is_present("NEW_TABLE") {
+        match client::http::HttpBackend::create_table(host) {
+            Ok(code) => {
+                println!("Created table {}: give this code to the other players.", code);
+                Some(code)
+            }
+            Err(err) => panic!("Could not create a table: {:?}", err),
+        }
+    } else {
+        matches.value_of("TABLE").map(|code| code.to_string())
+    };
+
+    match table {
+        Some(code) => {
+            let options = TableJoinBody {
+                name: matches.value_of("NAME").map(|name| name.to_string()),
+                seat: read_number(matches, "SEAT", "seat", 4)
+                          .map(|n| pos::PlayerPos::from_n(n as usize)),
+                // Team 0 is P0 & P2, team 1 is P1 & P3.
+                team: read_number(matches, "TEAM", "team", 2)
+                          .map(|n| pos::PlayerPos::from_n(n as usize).team()),
+            };
+            retry_join(|| client::http::HttpBackend::join_table(host, &code, &options, login))
+        }
+        None => {
+            let options = JoinBody {
+                name: matches.value_of("NAME").map(|name| name.to_string()),
+                bots_after: read_number(matches, "BOTS", "bot delay", std::u32::MAX),
+            };
+            retry_join(|| client::http::HttpBackend::join(host, &options, login))
+        }
+    }
+}
+
+/// Reads the player ID and token saved in the session file, if any.
+fn read_session(path: &str) -> Option<(u32, String)> {
+    let mut content = String::new();
+    if fs::File::open(path).and_then(|mut file| file.read_to_string(&mut content)).is_err() {
+        return None;
+    }
+
+    let tokens: Vec<&str> = content.split_whitespace().collect();
+    if tokens.len() != 2 {
+        return None;
+    }
+    u32::from_str(tokens[0]).ok().map(|player_id| (player_id, tokens[1].to_string()))
+}
+
+/// Saves the player ID and token in the session file, to resume after a crash.
+fn write_session(path: &str, player_id: u32, token: &str) {
+    match fs::File::create(path).and_then(|mut file| write!(file, "{} {}", player_id, token)) {
+        Ok(_) => (),
+        Err(err) => println!("Could not save the session: {}", err),
+    }
+}
+
+/// Downloads the replay of a party, and saves it in the given file.
+fn save_replay(path: &str, host: &str, player_id: u32, token: &str) {
+    let replay = match client::http::HttpBackend::fetch_replay(host, player_id, token) {
+        Ok(replay) => replay,
+        Err(err) => {
+            println!("Could not get the replay: {:?}", err);
+            return;
+        }
+    };
+
+    match fs::File::create(path).and_then(|mut file| write!(file, "{}", replay.to_json())) {
+        Ok(_) => println!("Replay saved in {}", path),
+        Err(err) => println!("Could not save the replay: {}", err),
+    }
+}
+
+/// Prints the hand of every player.
+fn print_hands(names: &[String], hands: &[cards::Hand; 4]) {
+    for (name, hand) in names.iter().zip(hands.iter()) {
+        let cards: Vec<String> = hand.list().into_iter().map(|card| card.to_string()).collect();
+        println!("{:>12}: [ {} ]", name, cards.join(" "));
+    }
+}
+
+/// Steps through a replay file, as asked by the user.
+fn run_replay(matches: &clap::ArgMatches) {
+    let path = matches.value_of("FILE").unwrap();
+
+    let mut content = String::new();
+    if let Err(err) = fs::File::open(path).and_then(|mut file| file.read_to_string(&mut content)) {
+        println!("Could not read {}: {}", path, err);
+        std::process::exit(1);
+    }
+
+    let replay = match Replay::from_json(&content) {
+        Ok(replay) => replay,
+        Err(err) => {
+            println!("{}", err);
+            std::process::exit(1);
+        }
+    };
+
+    let seat = read_number(matches, "SEAT", "seat", 4).unwrap_or(0);
+    let seat = pos::PlayerPos::from_n(seat as usize);
+    let all_hands = matches.is_present("ALL_HANDS");
+
+    let mut viewer = match client::replay::ReplayViewer::new(&replay, seat) {
+        Ok(viewer) => viewer,
+        Err(err) => {
+            println!("{}", err);
+            std::process::exit(1);
+        }
+    };
+
+    if viewer.deals().is_empty() {
+        println!("No finished deal in this replay.");
+        return;
+    }
+
+    let mut frontend = CliFrontend::new(seat, replay.names.clone());
+    // The frontend is only used to display things: any backend type would do.
+    viewer.show::<client::http::HttpBackend, _>(&mut frontend);
+
+    loop {
+        let (deal, step) = viewer.position();
+        if all_hands {
+            print_hands(&replay.names, &viewer.hands());
+        }
+        print!("[deal {}/{}, trick {}] (n)ext, (p)revious, (q)uit\n> ",
+               deal + 1,
+               viewer.deals().len(),
+               step);
+        io::stdout().flush().unwrap();
+
+        let moved = match CliFrontend::input().as_ref() {
+            "" | "n" => viewer.forward::<client::http::HttpBackend, _>(&mut frontend),
+            "p" => viewer.backward::<client::http::HttpBackend, _>(&mut frontend),
+            "q" => return,
+            _ => {
+                println!("Invalid input.");
+                continue;
+            }
+        };
+
+        if !moved {
+            println!("Nothing more this way.");
+        }
+    }
+}
+
+/// Lists the parties going on on the server.
+fn run_parties(host: &str) {
+    let parties = match client::http::HttpSpectator::list_parties(host) {
+        Ok(parties) => parties,
+        Err(err) => {
+            println!("Could not list the parties: {:?}", err);
+            std::process::exit(1);
+        }
+    };
+
+    if parties.is_empty() {
+        println!("No party going on.");
+    }
+    for party in parties {
+        println!("Party {}: {} (scores: {:?}, {} deals)",
+                 party.party_id,
+                 party.names.join(", "),
+                 party.scores,
+                 party.deals);
+    }
+}
+
+/// Follows a party as a spectator, printing every event.
+fn run_watch(host: &str, matches: &clap::ArgMatches) {
+    let party_id = read_number(matches, "PARTY_ID", "party ID", std::u32::MAX).unwrap();
+
+    let mut spectator = match client::http::HttpSpectator::spectate(host, party_id) {
+        Ok(spectator) => spectator,
+        Err(err) => {
+            println!("Could not watch the party: {:?}", err);
+            std::process::exit(1);
+        }
+    };
+
+    if let Some(delay) = spectator.delay {
+        println!("Events are shown {} seconds late.", delay / 1000);
+    }
+
+    let names = spectator.names.clone();
+    let name = |pos: pos::PlayerPos| &names[pos as usize];
+    loop {
+        let event = match spectator.wait() {
+            Ok(event) => event,
+            Err(err) => {
+                println!("Error: {:?}", err);
+                return;
+            }
+        };
+
+        match event {
+            EventType::NewGameSpectated { first, hands } => {
+                println!("New deal. First player: {}", name(first));
+                if let Some(ref hands) = hands {
+                    print_hands(&names, hands);
+                }
+            }
+            EventType::FromPlayer(pos, PlayerEvent::Bidded(suit, target)) => {
+                println!("{} bid {} on {}",
+                         name(pos),
+                         target.to_string(),
+                         suit.to_string())
+            }
+            EventType::FromPlayer(pos, PlayerEvent::Passed) => println!("{} passed", name(pos)),
+            EventType::FromPlayer(pos, PlayerEvent::Coinched) => {
+                println!("{} coinched", name(pos))
+            }
+            EventType::FromPlayer(pos, PlayerEvent::CardPlayed(card)) => {
+                println!("{} played {}", name(pos), card.to_string())
+            }
+            EventType::BidOver(contract) => println!("Auction is over: {:?}", contract),
+            EventType::BidCancelled => println!("Auction cancelled!"),
+            EventType::TrickOver { winner } => println!("{} gets the trick.", name(winner)),
+            EventType::GameOver { points, winner, scores } => {
+                println!("{:?} won. Points were {:?} ; scores: {:?}",
+                         winner,
+                         points,
+                         scores)
+            }
+            EventType::PartyCancelled(msg) => {
+                println!("Party cancelled: {}", msg);
+                return;
+            }
+            event => println!("Unexpected event: {:?}", event),
+        }
+    }
+}
+
+/// Plays a party without any server, against bots.
+///
+/// With several names, the players share the terminal (hot-seat).
+fn run_offline(matches: &clap::ArgMatches) {
+    let names: Vec<Option<String>> = match matches.values_of("NAMES") {
+        Some(names) => names.iter().map(|name| Some(name.to_string())).collect(),
+        None => vec![None],
+    };
+    if names.len() > 4 {
+        println!("At most 4 players can sit at a table.");
+        std::process::exit(1);
+    }
+
+    let mut settings = Settings::default();
+    settings.target_score = matches.value_of("TARGET_SCORE").map(|score| {
+        match i32::from_str(score) {
+            Ok(score) => score,
+            Err(err) => {
+                println!("Invalid target score: `{}` ({})", score, err);
+                std::process::exit(1);
+            }
+        }
+    });
+
+    let manager = match GameManager::new(settings) {
+        Ok(manager) => Arc::new(manager),
+        Err(err) => {
+            println!("Could not start the party: {}", err);
+            std::process::exit(1);
+        }
+    };
+    let parties = match manager.start_local_party(&names) {
+        Ok(parties) => parties,
+        Err(err) => {
+            println!("Could not start the party: {}", err);
+            std::process::exit(1);
+        }
+    };
+
+    let terminal = if parties.len() > 1 {
+        println!("Hot-seat party: everything stays hidden until each player's turn.");
+        Some(Arc::new(Mutex::new(())))
+    } else {
+        None
+    };
+
+    // Each player runs in its own thread, like it would on its own client.
+    let mut players = Vec::new();
+    for party in parties {
+        let pos = party.player_pos;
+        let names = party.names.clone();
+        let backend = client::local::LocalBackend::new(manager.clone(), party);
+        let mut frontend = match terminal {
+            Some(ref terminal) => CliFrontend::new_hot_seat(pos, names, terminal.clone()),
+            None => CliFrontend::new(pos, names),
+        };
+        players.push(thread::spawn(move || client::Client::new(backend).run(&mut frontend)));
+    }
+
+    // Everyone saw the same party: any score will do.
+    let mut scores = None;
+    for player in players {
+        if let Ok(result) = player.join() {
+            scores = scores.or(Some(result));
+        }
+    }
+    if let Some(scores) = scores {
+        println!("Final score: {:?}", scores);
+    }
+}
+
+/// Plays the party with the given backend, and returns the final scores.
+fn play<B: client::Backend>(backend: B,
+                            pos: pos::PlayerPos,
+                            names: Vec<String>,
+                            bot: bool)
+                            -> [i32; 2]
+    where B::Error: fmt::Debug
+{
+    if bot {
+        let mut frontend = client::bot::BotFrontend::new(pos);
+        client::Client::new(backend).run(&mut frontend)
+    } else {
+        let mut frontend = CliFrontend::new(pos, names);
+        client::Client::new(backend).run(&mut frontend)
     }
 }
 
@@ -187,17 +678,182 @@ fn main() {
                       .author("Alexandre Bury <alexandre.bury@gmail.com>")
                       .about("A client for coinched")
                       .arg(Arg::with_name("HOST")
-                               .help("Specifies the host to connect to")
-                               .required(true)
+                               .help("Specifies the host to connect to, like `localhost:3000` (or \
+                                      `https://myserver:3000` for HTTPS)")
                                .index(1))
+                      .arg(Arg::with_name("NAME")
+                               .help("Name to display to the other players")
+                               .short("n")
+                               .long("name")
+                               .takes_value(true))
+                      .arg(Arg::with_name("LOGIN")
+                               .help("Account to play as (the password is read from stdin)")
+                               .short("l")
+                               .long("login")
+                               .takes_value(true))
+                      .arg(Arg::with_name("REGISTER")
+                               .help("Create the account given with --login first")
+                               .long("register")
+                               .requires("LOGIN"))
+                      .arg(Arg::with_name("SESSION")
+                               .help("File keeping the player ID and token, to resume the party \
+                                      after a disconnection")
+                               .short("s")
+                               .long("session")
+                               .takes_value(true))
+                      .arg(Arg::with_name("BOTS")
+                               .help("Seconds to wait before filling empty seats with bots \
+                                      (0 to start right away)")
+                               .short("b")
+                               .long("bots")
+                               .takes_value(true))
+                      .arg(Arg::with_name("REPLAY")
+                               .help("Save the party as a replay file once it is over")
+                               .short("r")
+                               .long("replay")
+                               .takes_value(true))
+                      .arg(Arg::with_name("WS")
+                               .help("Receive events on the server WebSocket interface, like \
+                                      `ws://localhost:3001`")
+                               .long("ws")
+                               .takes_value(true))
+                      .arg(Arg::with_name("BOT")
+                               .help("Let a bot play instead of asking the user")
+                               .long("bot"))
+                      .arg(Arg::with_name("NEW_TABLE")
+                               .help("Create a private table, and sit at it")
+                               .long("new-table")
+                               .conflicts_with("TABLE"))
+                      .arg(Arg::with_name("TABLE")
+                               .help("Sit at the private table with the given code")
+                               .short("t")
+                               .long("table")
+                               .takes_value(true))
+                      .arg(Arg::with_name("SEAT")
+                               .help("Seat to take at a private table (0-3)")
+                               .long("seat")
+                               .takes_value(true))
+                      .arg(Arg::with_name("TEAM")
+                               .help("Team to join at a private table (0 or 1)")
+                               .long("team")
+                               .takes_value(true))
+                      .subcommand(SubCommand::with_name("replay")
+                                      .about("Steps through a replay file, trick by trick")
+                                      .arg(Arg::with_name("FILE")
+                                               .help("Replay file to show")
+                                               .required(true)
+                                               .index(1))
+                                      .arg(Arg::with_name("SEAT")
+                                               .help("Seat to watch the party from (0-3, \
+                                                      defaults to 0)")
+                                               .long("seat")
+                                               .takes_value(true))
+                                      .arg(Arg::with_name("ALL_HANDS")
+                                               .help("Show the hand of every player")
+                                               .short("a")
+                                               .long("all-hands")))
+                      .subcommand(SubCommand::with_name("offline")
+                                      .about("Plays without a server, against bots. With \
+                                              several names, players take turns at the \
+                                              terminal")
+                                      .arg(Arg::with_name("NAMES")
+                                               .help("Name of each human player (1 to 4)")
+                                               .multiple(true)
+                                               .index(1))
+                                      .arg(Arg::with_name("TARGET_SCORE")
+                                               .help("Score ending the party (by default, it \
+                                                      goes on until a player leaves)")
+                                               .long("target-score")
+                                               .takes_value(true)))
+                      .subcommand(SubCommand::with_name("parties")
+                                      .about("Lists the parties going on on HOST"))
+                      .subcommand(SubCommand::with_name("watch")
+                                      .about("Watches a party on HOST, without a seat")
+                                      .arg(Arg::with_name("PARTY_ID")
+                                               .help("Party to watch, as given by `parties`")
+                                               .required(true)
+                                               .index(1)))
                       .get_matches();
-    let host = matches.value_of("HOST").unwrap();
 
-    // TODO: allow reconnecting to an existing game
+    if let Some(matches) = matches.subcommand_matches("replay") {
+        run_replay(matches);
+        return;
+    }
+    if let Some(matches) = matches.subcommand_matches("offline") {
+        run_offline(matches);
+        return;
+    }
+
+    let host = match matches.value_of("HOST") {
+        Some(host) => host,
+        None => {
+            println!("A host is needed to play online (or try `coincher offline`). Run with \
+                      --help for more information.");
+            std::process::exit(1);
+        }
+    };
 
-    let backend = client::http::HttpBackend::join(host).unwrap();
-    let mut frontend = CliFrontend::new(backend.pos);
+    if matches.subcommand_matches("parties").is_some() {
+        run_parties(host);
+        return;
+    }
+    if let Some(matches) = matches.subcommand_matches("watch") {
+        run_watch(host, matches);
+        return;
+    }
 
-    println!("Final score: {:?}",
-             client::Client::new(backend).run(&mut frontend));
+    let session = matches.value_of("SESSION");
+
+    let backend = match session.and_then(read_session) {
+        Some((player_id, token)) => {
+            match client::http::HttpBackend::resume(host, player_id, &token) {
+                Ok((backend, _)) => backend,
+                Err(err) => {
+                    println!("Could not resume the party: {:?}", err);
+                    join(host, &matches)
+                }
+            }
+        }
+        None => join(host, &matches),
+    };
+
+    if let Some(path) = session {
+        write_session(path, backend.player_id(), backend.token());
+    }
+
+    // The client keeps the backend: remember how to get the replay.
+    let party = (backend.host().to_string(), backend.player_id(), backend.token().to_string());
+
+    let bot = matches.is_present("BOT");
+    let scores = match matches.value_of("WS") {
+        Some(url) => {
+            match client::websocket::WsBackend::connect(url,
+                                                         backend.player_id(),
+                                                         backend.token(),
+                                                         backend.event_id(),
+                                                         backend.pos) {
+                Ok(ws) => play(ws, backend.pos, backend.names.clone(), bot),
+                Err(err) => {
+                    println!("Could not connect to {}: {:?}", url, err);
+                    std::process::exit(1);
+                }
+            }
+        }
+        None => {
+            let (pos, names) = (backend.pos, backend.names.clone());
+            play(backend, pos, names, bot)
+        }
+    };
+
+    println!("Final score: {:?}", scores);
+
+    if let Some(path) = matches.value_of("REPLAY") {
+        let (ref host, player_id, ref token) = party;
+        save_replay(path, host, player_id, token);
+    }
+
+    // The party is over, nothing to resume.
+    if let Some(path) = session {
+        fs::remove_file(path).ok();
+    }
 }
\ No newline at end of file
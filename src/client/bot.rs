@@ -0,0 +1,106 @@
+//! A frontend that plays on its own.
+
+use std::fmt;
+
+use libcoinche::{bid, cards, pos, trick};
+use EventType;
+use ai;
+
+use super::{Backend, Frontend, AuctionAction, GameAction};
+
+/// Automatic player.
+///
+/// It follows the game to know its hand and the current trick,
+/// and always plays legal cards.
+pub struct BotFrontend {
+    pos: pos::PlayerPos,
+    hand: cards::Hand,
+    // First player of the current deal
+    first: pos::PlayerPos,
+    trick: trick::Trick,
+    // Did anyone bid in the current auction?
+    opened: bool,
+    // Known once the auction is over
+    contract: Option<bid::Contract>,
+}
+
+impl BotFrontend {
+    pub fn new(pos: pos::PlayerPos) -> Self {
+        BotFrontend {
+            pos: pos,
+            hand: cards::Hand::new(),
+            first: pos,
+            trick: trick::Trick::new(pos),
+            opened: false,
+            contract: None,
+        }
+    }
+
+    fn trump(&self) -> cards::Suit {
+        self.contract.as_ref().expect("no contract yet").trump
+    }
+}
+
+impl<B: Backend> Frontend<B> for BotFrontend
+    where B::Error: fmt::Debug
+{
+    fn show_error(&mut self, error: B::Error) {
+        warn!("Bot {:?}: {:?}", self.pos, error);
+    }
+
+    fn unexpected_event(&mut self, event: EventType) {
+        warn!("Bot {:?}: unexpected event {:?}", self.pos, event);
+    }
+
+    fn party_cancelled(&mut self, msg: &str) {
+        trace!("Bot {:?}: party cancelled ({})", self.pos, msg);
+    }
+
+    fn show_card_played(&mut self, pos: pos::PlayerPos, card: cards::Card) {
+        if pos == self.pos {
+            self.hand.remove(card);
+        }
+        let trump = self.trump();
+        self.trick.play_card(pos, card, trump);
+    }
+
+    fn show_trick_over(&mut self, winner: pos::PlayerPos) {
+        self.trick = trick::Trick::new(winner);
+    }
+
+    fn ask_card(&mut self) -> GameAction {
+        let card = ai::choose_card(self.pos, self.hand, &self.trick, self.trump());
+        GameAction::PlayCard(card)
+    }
+
+    fn ask_bid(&mut self) -> AuctionAction {
+        match ai::choose_bid(self.hand, self.opened) {
+            Some(contract) => AuctionAction::Bid(contract),
+            None => AuctionAction::Pass,
+        }
+    }
+
+    fn game_over(&mut self, _: [i32; 2], _: pos::Team, _: [i32; 2]) {}
+
+    fn show_pass(&mut self, _: pos::PlayerPos) {}
+
+    fn show_coinche(&mut self, _: pos::PlayerPos) {}
+
+    fn show_bid(&mut self, _: pos::PlayerPos, _: cards::Suit, _: bid::Target) {
+        self.opened = true;
+    }
+
+    fn auction_cancelled(&mut self) {}
+
+    fn auction_over(&mut self, contract: &bid::Contract) {
+        self.contract = Some(contract.clone());
+        self.trick = trick::Trick::new(self.first);
+    }
+
+    fn start_game(&mut self, first: pos::PlayerPos, hand: cards::Hand) {
+        self.first = first;
+        self.hand = hand;
+        self.opened = false;
+        self.contract = None;
+    }
+}
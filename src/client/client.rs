@@ -30,6 +30,11 @@ impl<B: Backend> Client<B> {
                         _ => (),
                     }
                 }
+                // The party can also end between two deals.
+                Ok(EventType::PartyCancelled(msg)) => {
+                    frontend.party_cancelled(&msg);
+                    return self.scores;
+                }
                 Ok(event) => frontend.unexpected_event(event),
                 Err(err) => frontend.show_error(err),
             }
@@ -53,6 +58,9 @@ impl<B: Backend> Client<B> {
                 Ok(EventType::YourTurn) => {
                     event = match frontend.ask_bid() {
                         AuctionAction::Leave => {
+                            if let Err(err) = self.backend.leave() {
+                                frontend.show_error(err);
+                            }
                             frontend.party_cancelled("you left");
                             return Err(GameError::PlayerLeft);
                         }
@@ -103,6 +111,9 @@ impl<B: Backend> Client<B> {
                 Ok(EventType::YourTurn) => {
                     event = match frontend.ask_card() {
                         GameAction::Leave => {
+                            if let Err(err) = self.backend.leave() {
+                                frontend.show_error(err);
+                            }
                             frontend.party_cancelled("you left");
                             return Err(GameError::PlayerLeft);
                         }
@@ -1,33 +1,61 @@
 
 use std::io;
+use std::collections::VecDeque;
 use rustc_serialize::Decodable;
 use rustc_serialize::json;
 use hyper::client::IntoUrl;
 use std::io::Read;
 use libcoinche::pos;
-use hyper::header::ContentType;
+use hyper::header::{Authorization, ContentType};
 use hyper::mime::{Mime, TopLevel, SubLevel, Attr, Value};
 use url;
 use hyper;
 
-use {NewPartyInfo, Event, EventType, ContractBody, CardBody};
+use {NewPartyInfo, ResumeInfo, TableInfo, LoginInfo, DealRecord, AccountBody, JoinBody,
+     TableJoinBody, Event, EventType, ContractBody, CardBody, PartySummary, SpectatorInfo};
+use replay::Replay;
 
 use super::Backend;
 
 /// HTTP coinched client.
 ///
 /// Provides an abstraction over HTTP requests.
+///
+/// Hosts are given as `host:port`, or as a URL like `https://host:port` to use HTTPS.
 pub struct HttpBackend {
     player_id: u32,
+    // Proves we own the seat; sent with every request.
+    token: String,
     pub pos: pos::PlayerPos,
+    /// Name of the player on each seat.
+    pub names: Vec<String>,
 
     event_id: usize,
+    // Events received but not consumed yet.
+    pending: VecDeque<EventType>,
 
     host: String, /* It used to include a re-usable hyper::Client,
                    * but it would lead to failed request if too
                    * long happened between two queries. */
 }
 
+/// HTTP coinched spectator.
+///
+/// Follows a party without a seat: it can only wait for events.
+pub struct HttpSpectator {
+    spectator_id: u32,
+    /// Name of the player on each seat.
+    pub names: Vec<String>,
+    /// Delay of the events, in milliseconds, if the server hides them for a while.
+    pub delay: Option<u32>,
+
+    event_id: usize,
+    // Events received but not consumed yet.
+    pending: VecDeque<EventType>,
+
+    host: String,
+}
+
 #[derive(Debug)]
 pub enum Error {
     Url(url::ParseError),
@@ -36,6 +64,16 @@ pub enum Error {
     Coinched(::Error),
 }
 
+impl Error {
+    /// Returns the error code sent by the server, if any.
+    pub fn code(&self) -> Option<&str> {
+        match self {
+            &Error::Coinched(ref err) => err.code.as_ref().map(|code| &**code),
+            _ => None,
+        }
+    }
+}
+
 impl From<url::ParseError> for Error {
     fn from(err: url::ParseError) -> Self {
         Error::Url(err)
@@ -82,37 +120,179 @@ fn from_reader<R: Read, T: Decodable>(r: &mut R) -> Result<T, Error> {
     }
 }
 
-/// Leave the party on drop.
-/// TODO: handle "soft" exit with reconnection?
-impl Drop for HttpBackend {
-    fn drop(&mut self) {
-        let leave_url = format!("http://{}/leave/{}", self.host, self.player_id);
-        hyper::Client::new().post(&leave_url).send().unwrap();
+// Returns the URL of the server root.
+//
+// Hosts without a scheme are reached over plain HTTP.
+fn base_url(host: &str) -> String {
+    if host.starts_with("http://") || host.starts_with("https://") {
+        host.trim_right_matches('/').to_string()
+    } else {
+        format!("http://{}", host)
     }
 }
 
+// Builds the header proving our identity to the server.
+fn bearer(token: &str) -> Authorization<String> {
+    Authorization(format!("Bearer {}", token))
+}
+
+// JSON content type, used for every request with a body.
+fn json_type() -> ContentType {
+    ContentType(Mime(TopLevel::Application,
+                     SubLevel::Json,
+                     vec![(Attr::Charset, Value::Utf8)]))
+}
+
 impl HttpBackend {
     /// Creates a client to connect to the given server, once logged in.
-    fn new(host: &str, player_id: u32, pos: pos::PlayerPos) -> Self {
+    fn new(host: &str, party: NewPartyInfo) -> Self {
 
         HttpBackend {
-            player_id: player_id,
-            pos: pos,
+            player_id: party.player_id,
+            token: party.token,
+            pos: party.player_pos,
+            names: party.names,
             event_id: 0,
+            pending: VecDeque::new(),
             host: host.to_string(),
         }
     }
 
     /// Attempt to join a game on the given host.
-    pub fn join(host: &str) -> Result<Self, Error> {
+    ///
+    /// If `login` is a token from `HttpBackend::login`, the seat is tied to that account.
+    pub fn join(host: &str, options: &JoinBody, login: Option<&str>) -> Result<Self, Error> {
         let client = hyper::Client::new();
 
-        let join_url = try!(format!("http://{}/join", host).into_url());
+        let join_url = try!(format!("{}/join", base_url(host)).into_url());
         println!("Connecting to {}", host);
-        let mut response = try!(client.post(join_url).send());
+        let body = json::encode(options).unwrap();
+        let mut request = client.post(join_url).header(json_type()).body(&body);
+        if let Some(token) = login {
+            request = request.header(bearer(token));
+        }
+        let mut response = try!(request.send());
+        let party: NewPartyInfo = try!(from_reader(&mut response));
+
+        Ok(HttpBackend::new(host, party))
+    }
+
+    /// Create a private table on the given host. Returns its code.
+    pub fn create_table(host: &str) -> Result<String, Error> {
+        let client = hyper::Client::new();
+
+        let table_url = try!(format!("{}/table", base_url(host)).into_url());
+        let mut response = try!(client.post(table_url).send());
+        let table: TableInfo = try!(from_reader(&mut response));
+
+        Ok(table.code)
+    }
+
+    /// Attempt to sit at a private table on the given host.
+    pub fn join_table(host: &str,
+                      code: &str,
+                      options: &TableJoinBody,
+                      login: Option<&str>)
+                      -> Result<Self, Error> {
+        let client = hyper::Client::new();
+
+        let table_url = try!(format!("{}/table/{}", base_url(host), code).into_url());
+        println!("Joining table {} on {}", code, host);
+        let body = json::encode(options).unwrap();
+        let mut request = client.post(table_url).header(json_type()).body(&body);
+        if let Some(token) = login {
+            request = request.header(bearer(token));
+        }
+        let mut response = try!(request.send());
         let party: NewPartyInfo = try!(from_reader(&mut response));
 
-        Ok(HttpBackend::new(host, party.player_id, party.player_pos))
+        Ok(HttpBackend::new(host, party))
+    }
+
+    /// Register a new account on the given host.
+    pub fn register(host: &str, account: &AccountBody) -> Result<(), Error> {
+        let client = hyper::Client::new();
+
+        let register_url = try!(format!("{}/register", base_url(host)).into_url());
+        let body = json::encode(account).unwrap();
+        let mut response = try!(client.post(register_url).header(json_type()).body(&body).send());
+        let _: String = try!(from_reader(&mut response));
+
+        Ok(())
+    }
+
+    /// Log in an existing account on the given host. Returns the login token.
+    pub fn login(host: &str, account: &AccountBody) -> Result<String, Error> {
+        let client = hyper::Client::new();
+
+        let login_url = try!(format!("{}/login", base_url(host)).into_url());
+        let body = json::encode(account).unwrap();
+        let mut response = try!(client.post(login_url).header(json_type()).body(&body).send());
+        let info: LoginInfo = try!(from_reader(&mut response));
+
+        Ok(info.token)
+    }
+
+    /// Attempt to get back in a party we were already playing.
+    ///
+    /// The next events will replay the current deal from its start.
+    pub fn resume(host: &str, player_id: u32, token: &str) -> Result<(Self, ResumeInfo), Error> {
+        let client = hyper::Client::new();
+
+        let resume_url = try!(format!("{}/resume/{}", base_url(host), player_id).into_url());
+        println!("Resuming on {}", host);
+        let mut response = try!(client.post(resume_url).header(bearer(token)).send());
+        let info: ResumeInfo = try!(from_reader(&mut response));
+
+        let mut backend = HttpBackend::new(host, info.party.clone());
+        backend.event_id = info.deal_event_id;
+
+        Ok((backend, info))
+    }
+
+    /// Returns the ID given by the server, needed to resume later.
+    pub fn player_id(&self) -> u32 {
+        self.player_id
+    }
+
+    /// Returns the token owning our seat, needed to resume later.
+    pub fn token(&self) -> &str {
+        &self.token
+    }
+
+    /// Returns every finished deal of the party, oldest first.
+    pub fn history(&self) -> Result<Vec<DealRecord>, Error> {
+        let history_url = format!("{}/history/{}", base_url(&self.host), self.player_id);
+        let mut response = try!(hyper::Client::new()
+                                    .get(&history_url)
+                                    .header(bearer(&self.token))
+                                    .send());
+        from_reader(&mut response)
+    }
+
+    /// Exports the party as a replay.
+    pub fn replay(&self) -> Result<Replay, Error> {
+        HttpBackend::fetch_replay(&self.host, self.player_id, &self.token)
+    }
+
+    /// Exports a party as a replay, even after it is over.
+    pub fn fetch_replay(host: &str, player_id: u32, token: &str) -> Result<Replay, Error> {
+        let replay_url = format!("{}/replay/{}", base_url(host), player_id);
+        let mut response = try!(hyper::Client::new()
+                                    .get(&replay_url)
+                                    .header(bearer(token))
+                                    .send());
+        from_reader(&mut response)
+    }
+
+    /// Returns the ID of the next event to receive.
+    pub fn event_id(&self) -> usize {
+        self.event_id
+    }
+
+    /// Returns the host we are connected to.
+    pub fn host(&self) -> &str {
+        &self.host
     }
 
     /// Parse and return an event from the given reader.
@@ -128,50 +308,147 @@ impl HttpBackend {
 impl Backend for HttpBackend {
     type Error = Error;
 
+    /// Returns the next event.
+    ///
+    /// Fetches every available event at once, so it catches up in a single request.
     fn wait(&mut self) -> Result<EventType, Error> {
-        let wait_url = format!("http://{}/wait/{}/{}",
-                               &self.host,
-                               self.player_id,
-                               self.event_id);
-        let mut response = try!(hyper::Client::new().get(&wait_url).send());
-        self.read_event(&mut response)
+        if let Some(event) = self.pending.pop_front() {
+            return Ok(event);
+        }
+
+        loop {
+            let events_url = format!("{}/events/{}/{}",
+                                     base_url(&self.host),
+                                     self.player_id,
+                                     self.event_id);
+            let mut response = try!(hyper::Client::new()
+                                        .get(&events_url)
+                                        .header(bearer(&self.token))
+                                        .send());
+            match from_reader::<_, Vec<Event>>(&mut response) {
+                Ok(events) => {
+                    for event in events {
+                        self.event_id = event.id + 1;
+                        self.pending.push_back(event.event);
+                    }
+                    match self.pending.pop_front() {
+                        Some(event) => return Ok(event),
+                        None => continue,
+                    }
+                }
+                // Nothing happened yet: just ask again.
+                Err(ref err) if err.code() == Some("wait_timeout") => continue,
+                Err(err) => return Err(err),
+            }
+        }
     }
 
     fn bid(&mut self, contract: ContractBody) -> Result<EventType, Error> {
-        let bid_url = format!("http://{}/bid/{}", self.host, self.player_id);
+        let bid_url = format!("{}/bid/{}", base_url(&self.host), self.player_id);
         let body = json::encode(&contract).unwrap();
         let mut response = try!(hyper::Client::new()
                                     .post(&bid_url)
-                                    .header(ContentType(Mime(TopLevel::Application,
-                                                             SubLevel::Json,
-                                                             vec![(Attr::Charset, Value::Utf8)])))
+                                    .header(json_type())
+                                    .header(bearer(&self.token))
                                     .body(&body)
                                     .send());
         self.read_event(&mut response)
     }
 
     fn pass(&mut self) -> Result<EventType, Error> {
-        let pass_url = format!("http://{}/pass/{}", self.host, self.player_id);
-        let mut response = try!(hyper::Client::new().post(&pass_url).send());
+        let pass_url = format!("{}/pass/{}", base_url(&self.host), self.player_id);
+        let mut response = try!(hyper::Client::new()
+                                    .post(&pass_url)
+                                    .header(bearer(&self.token))
+                                    .send());
         self.read_event(&mut response)
     }
 
     fn coinche(&mut self) -> Result<EventType, Error> {
-        let coinche_url = format!("http://{}/coinche/{}", self.host, self.player_id);
-        let mut response = try!(hyper::Client::new().post(&coinche_url).send());
+        let coinche_url = format!("{}/coinche/{}", base_url(&self.host), self.player_id);
+        let mut response = try!(hyper::Client::new()
+                                    .post(&coinche_url)
+                                    .header(bearer(&self.token))
+                                    .send());
         self.read_event(&mut response)
     }
 
     fn play_card(&mut self, card: CardBody) -> Result<EventType, Error> {
-        let play_url = format!("http://{}/play/{}", self.host, self.player_id);
+        let play_url = format!("{}/play/{}", base_url(&self.host), self.player_id);
         let body = json::encode(&card).unwrap();
         let mut response = try!(hyper::Client::new()
                                     .post(&play_url)
-                                    .header(ContentType(Mime(TopLevel::Application,
-                                                             SubLevel::Json,
-                                                             vec![(Attr::Charset, Value::Utf8)])))
+                                    .header(json_type())
+                                    .header(bearer(&self.token))
                                     .body(&body)
                                     .send());
         self.read_event(&mut response)
     }
-}
\ No newline at end of file
+
+    fn leave(&mut self) -> Result<(), Error> {
+        let leave_url = format!("{}/leave/{}", base_url(&self.host), self.player_id);
+        let mut response = try!(hyper::Client::new()
+                                    .post(&leave_url)
+                                    .header(bearer(&self.token))
+                                    .send());
+        let _: String = try!(from_reader(&mut response));
+        Ok(())
+    }
+}
+
+impl HttpSpectator {
+    /// Lists the parties going on on the given host.
+    pub fn list_parties(host: &str) -> Result<Vec<PartySummary>, Error> {
+        let parties_url = format!("{}/parties", base_url(host));
+        let mut response = try!(hyper::Client::new().get(&parties_url).send());
+        from_reader(&mut response)
+    }
+
+    /// Starts watching a party on the given host.
+    ///
+    /// The first events replay the current deal from its start.
+    pub fn spectate(host: &str, party_id: u32) -> Result<Self, Error> {
+        let spectate_url = try!(format!("{}/spectate/{}", base_url(host), party_id).into_url());
+        let mut response = try!(hyper::Client::new().post(spectate_url).send());
+        let info: SpectatorInfo = try!(from_reader(&mut response));
+
+        Ok(HttpSpectator {
+            spectator_id: info.spectator_id,
+            names: info.names,
+            delay: info.delay,
+            event_id: info.deal_event_id,
+            pending: VecDeque::new(),
+            host: host.to_string(),
+        })
+    }
+
+    /// Returns the next event of the party.
+    pub fn wait(&mut self) -> Result<EventType, Error> {
+        if let Some(event) = self.pending.pop_front() {
+            return Ok(event);
+        }
+
+        loop {
+            let watch_url = format!("{}/watch/{}/{}",
+                                    base_url(&self.host),
+                                    self.spectator_id,
+                                    self.event_id);
+            let mut response = try!(hyper::Client::new().get(&watch_url).send());
+            match from_reader::<_, Vec<Event>>(&mut response) {
+                Ok(events) => {
+                    for event in events {
+                        self.event_id = event.id + 1;
+                        self.pending.push_back(event.event);
+                    }
+                    match self.pending.pop_front() {
+                        Some(event) => return Ok(event),
+                        None => continue,
+                    }
+                }
+                // Nothing to see yet: just ask again.
+                Err(ref err) if err.code() == Some("wait_timeout") => continue,
+                Err(err) => return Err(err),
+            }
+        }
+    }
+}
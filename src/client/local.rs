@@ -0,0 +1,375 @@
+use std::collections::VecDeque;
+use std::sync::Arc;
+use libcoinche::pos;
+
+use {NewPartyInfo, JoinBody, TableJoinBody, Event, EventType, ContractBody, CardBody};
+use server::error::Error;
+use server::game_manager::GameManager;
+
+use super::Backend;
+
+/// In-process coinched client.
+///
+/// Calls the game manager directly, without any server in between.
+/// Joining blocks until the party starts, so each player needs its own thread.
+pub struct LocalBackend {
+    manager: Arc<GameManager>,
+    player_id: u32,
+    // Proves we own the seat.
+    token: String,
+    pub pos: pos::PlayerPos,
+    /// Name of the player on each seat.
+    pub names: Vec<String>,
+
+    event_id: usize,
+    // Events received but not consumed yet.
+    pending: VecDeque<EventType>,
+}
+
+impl LocalBackend {
+    /// Creates a client for a seat already taken on the given manager.
+    pub fn new(manager: Arc<GameManager>, party: NewPartyInfo) -> Self {
+        LocalBackend {
+            manager: manager,
+            player_id: party.player_id,
+            token: party.token,
+            pos: party.player_pos,
+            names: party.names,
+            event_id: 0,
+            pending: VecDeque::new(),
+        }
+    }
+
+    /// Attempt to join a game on the given manager.
+    ///
+    /// If `login` is a login token, the seat is tied to that account.
+    pub fn join(manager: Arc<GameManager>,
+                options: JoinBody,
+                login: Option<&str>)
+                -> Result<Self, Error> {
+        let party = try!(manager.join(options, login));
+        Ok(LocalBackend::new(manager, party))
+    }
+
+    /// Attempt to sit at a private table on the given manager.
+    pub fn join_table(manager: Arc<GameManager>,
+                      code: &str,
+                      options: TableJoinBody,
+                      login: Option<&str>)
+                      -> Result<Self, Error> {
+        let party = try!(manager.join_table(code, options, login));
+        Ok(LocalBackend::new(manager, party))
+    }
+
+    /// Returns the ID given by the manager.
+    pub fn player_id(&self) -> u32 {
+        self.player_id
+    }
+
+    /// Returns the token owning our seat.
+    pub fn token(&self) -> &str {
+        &self.token
+    }
+
+    // Remembers the event caused by an action.
+    fn read_event(&mut self, event: Event) -> EventType {
+        self.event_id = event.id + 1;
+        event.event
+    }
+}
+
+impl Backend for LocalBackend {
+    type Error = Error;
+
+    /// Returns the next event.
+    ///
+    /// Fetches every available event at once, like `HttpBackend`.
+    fn wait(&mut self) -> Result<EventType, Error> {
+        if let Some(event) = self.pending.pop_front() {
+            return Ok(event);
+        }
+
+        loop {
+            match self.manager.wait_events(self.player_id, &self.token, self.event_id) {
+                Ok(events) => {
+                    for event in events {
+                        self.event_id = event.id + 1;
+                        self.pending.push_back(event.event);
+                    }
+                    match self.pending.pop_front() {
+                        Some(event) => return Ok(event),
+                        None => continue,
+                    }
+                }
+                // Nothing happened yet: just ask again.
+                Err(Error::WaitTimeout) => continue,
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    fn bid(&mut self, contract: ContractBody) -> Result<EventType, Error> {
+        let event = try!(self.manager.bid(self.player_id, &self.token, contract));
+        Ok(self.read_event(event))
+    }
+
+    fn pass(&mut self) -> Result<EventType, Error> {
+        let event = try!(self.manager.pass(self.player_id, &self.token));
+        Ok(self.read_event(event))
+    }
+
+    fn coinche(&mut self) -> Result<EventType, Error> {
+        let event = try!(self.manager.coinche(self.player_id, &self.token));
+        Ok(self.read_event(event))
+    }
+
+    fn play_card(&mut self, card: CardBody) -> Result<EventType, Error> {
+        let event = try!(self.manager.play_card(self.player_id, &self.token, card));
+        Ok(self.read_event(event))
+    }
+
+    fn leave(&mut self) -> Result<(), Error> {
+        self.manager.leave(self.player_id, &self.token)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::thread;
+
+    use libcoinche::{bid, cards, game, pos};
+
+    use {EventType, PlayerEvent, JoinBody};
+    use ai;
+    use client::{Client, Frontend, AuctionAction, GameAction};
+    use client::bot::BotFrontend;
+    use server::error::Error;
+    use server::game_manager::{GameManager, Settings};
+    use super::LocalBackend;
+
+    const SEED: u32 = 42;
+
+    // Plays like a bot, and counts what happens.
+    struct Recorder {
+        bot: BotFrontend,
+        cards: usize,
+        tricks: usize,
+        deals: usize,
+        scores: [i32; 2],
+        unexpected: usize,
+        cancelled: Option<String>,
+    }
+
+    impl Recorder {
+        fn new(pos: pos::PlayerPos) -> Self {
+            Recorder {
+                bot: BotFrontend::new(pos),
+                cards: 0,
+                tricks: 0,
+                deals: 0,
+                scores: [0; 2],
+                unexpected: 0,
+                cancelled: None,
+            }
+        }
+
+        fn bot(&mut self) -> &mut Frontend<LocalBackend> {
+            &mut self.bot
+        }
+    }
+
+    impl Frontend<LocalBackend> for Recorder {
+        fn show_error(&mut self, error: Error) {
+            panic!("unexpected error: {:?}", error);
+        }
+
+        fn unexpected_event(&mut self, event: EventType) {
+            self.unexpected += 1;
+            self.bot().unexpected_event(event);
+        }
+
+        fn party_cancelled(&mut self, msg: &str) {
+            self.cancelled = Some(msg.to_string());
+        }
+
+        fn show_card_played(&mut self, pos: pos::PlayerPos, card: cards::Card) {
+            self.cards += 1;
+            self.bot().show_card_played(pos, card);
+        }
+
+        fn show_trick_over(&mut self, winner: pos::PlayerPos) {
+            self.tricks += 1;
+            self.bot().show_trick_over(winner);
+        }
+
+        fn ask_card(&mut self) -> GameAction {
+            self.bot().ask_card()
+        }
+
+        fn ask_bid(&mut self) -> AuctionAction {
+            self.bot().ask_bid()
+        }
+
+        fn game_over(&mut self, points: [i32; 2], winner: pos::Team, scores: [i32; 2]) {
+            self.deals += 1;
+            for i in 0..2 {
+                self.scores[i] += scores[i];
+            }
+            self.bot().game_over(points, winner, scores);
+        }
+
+        fn show_pass(&mut self, pos: pos::PlayerPos) {
+            self.bot().show_pass(pos);
+        }
+
+        fn show_coinche(&mut self, pos: pos::PlayerPos) {
+            self.bot().show_coinche(pos);
+        }
+
+        fn show_bid(&mut self, pos: pos::PlayerPos, suit: cards::Suit, target: bid::Target) {
+            self.bot().show_bid(pos, suit, target);
+        }
+
+        fn auction_cancelled(&mut self) {
+            self.bot().auction_cancelled();
+        }
+
+        fn auction_over(&mut self, contract: &bid::Contract) {
+            self.bot().auction_over(contract);
+        }
+
+        fn start_game(&mut self, first: pos::PlayerPos, hand: cards::Hand) {
+            self.bot().start_game(first, hand);
+        }
+    }
+
+    // Plays the party with the rules alone, like the bots would, until the
+    // first finished deal. Returns its events, and the final scores.
+    fn expected_party(seed: u32) -> (Vec<EventType>, [i32; 2]) {
+        let mut events = Vec::new();
+        let mut first = pos::PlayerPos::P0;
+
+        loop {
+            // The manager numbers deals with their first event.
+            let hands = cards::deal_seeded_hands(&[seed, events.len() as u32]);
+            events.push(EventType::NewGame {
+                first: first,
+                hands: hands,
+            });
+
+            let mut auction = bid::Auction::new(first);
+            let mut opened = false;
+            let mut contract = None;
+            loop {
+                let pos = auction.next_player();
+                let (event, state) = match ai::choose_bid(hands[pos as usize], opened) {
+                    Some((suit, target)) => {
+                        opened = true;
+                        (PlayerEvent::Bidded(suit, target), auction.bid(pos, suit, target))
+                    }
+                    None => (PlayerEvent::Passed, auction.pass(pos)),
+                };
+                events.push(EventType::FromPlayer(pos, event));
+                match state.unwrap() {
+                    bid::AuctionState::Over => {
+                        contract = Some(auction.complete().unwrap().contract().clone());
+                        break;
+                    }
+                    bid::AuctionState::Cancelled => break,
+                    _ => (),
+                }
+            }
+
+            let contract = match contract {
+                Some(contract) => contract,
+                None => {
+                    events.push(EventType::BidCancelled);
+                    first = first.next();
+                    continue;
+                }
+            };
+            events.push(EventType::BidOver(contract.clone()));
+
+            let mut game = game::GameState::new(first, hands, contract);
+            loop {
+                let pos = game.next_player();
+                let card = ai::choose_card(pos,
+                                           game.hands()[pos as usize],
+                                           game.current_trick(),
+                                           game.contract().trump);
+                events.push(EventType::FromPlayer(pos, PlayerEvent::CardPlayed(card)));
+                match game.play_card(pos, card).unwrap() {
+                    game::TrickResult::Nothing => (),
+                    game::TrickResult::TrickOver(winner, game::GameResult::Nothing) => {
+                        events.push(EventType::TrickOver { winner: winner });
+                    }
+                    game::TrickResult::TrickOver(winner,
+                                                 game::GameResult::GameOver{points,
+                                                                            winners,
+                                                                            scores}) => {
+                        events.push(EventType::TrickOver { winner: winner });
+                        events.push(EventType::GameOver {
+                            points: points,
+                            winner: winners,
+                            scores: scores,
+                        });
+                        let msg = format!("party over, final scores: {:?}", scores);
+                        events.push(EventType::PartyCancelled(msg));
+                        return (events, scores);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_four_bots() {
+        // Any finished deal ends the party.
+        let settings = Settings {
+            target_score: Some(1),
+            deal_seed: Some(SEED),
+            ..Settings::default()
+        };
+        let manager = Arc::new(GameManager::new(settings).unwrap());
+
+        let players: Vec<_> = (0..4)
+                                  .map(|_| {
+                                      let manager = manager.clone();
+                                      thread::spawn(move || {
+                                          let backend = LocalBackend::join(manager,
+                                                                           JoinBody::default(),
+                                                                           None)
+                                                            .unwrap();
+                                          let seat = (backend.player_id(),
+                                                      backend.token().to_string());
+                                          let mut recorder = Recorder::new(backend.pos);
+                                          let scores = Client::new(backend).run(&mut recorder);
+                                          (seat, scores, recorder)
+                                      })
+                                  })
+                                  .collect();
+        let results: Vec<((u32, String), [i32; 2], Recorder)> =
+            players.into_iter()
+                   .map(|player| player.join().unwrap())
+                   .collect();
+
+        let (expected, scores) = expected_party(SEED);
+        let (player_id, ref token) = results[0].0;
+        let replay = manager.export_replay(player_id, token).unwrap();
+        let events: Vec<EventType> = replay.events.iter().map(|e| e.event.clone()).collect();
+        assert_eq!(format!("{:?}", events), format!("{:?}", expected));
+        assert_eq!(replay.check().unwrap().len(), 1);
+
+        let msg = format!("party over, final scores: {:?}", scores);
+        for &(_, player_scores, ref recorder) in &results {
+            assert_eq!(player_scores, scores);
+            assert_eq!(recorder.scores, scores);
+            assert_eq!(recorder.deals, 1);
+            assert_eq!(recorder.tricks, 8);
+            assert_eq!(recorder.cards, 32);
+            assert_eq!(recorder.unexpected, 0);
+            assert_eq!(recorder.cancelled, Some(msg.clone()));
+        }
+    }
+}
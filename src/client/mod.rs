@@ -3,6 +3,11 @@ use libcoinche::{pos, bid, cards};
 use {EventType, ContractBody, CardBody};
 
 pub mod http;
+pub mod websocket;
+pub mod tcp;
+pub mod local;
+pub mod bot;
+pub mod replay;
 mod client;
 
 pub use self::client::Client;
@@ -63,4 +68,7 @@ pub trait Backend {
     fn coinche(&mut self) -> Result<EventType, Self::Error>;
 
     fn play_card(&mut self, card: CardBody) -> Result<EventType, Self::Error>;
+
+    /// Leave the party for good.
+    fn leave(&mut self) -> Result<(), Self::Error>;
 }
\ No newline at end of file
@@ -0,0 +1,161 @@
+//! Steps through a recorded party, trick by trick.
+
+use libcoinche::{cards, pos};
+use {PlayerEvent, DealRecord};
+use replay::{Replay, ReplayError};
+
+use super::{Backend, Frontend};
+
+/// Shows a replay through the usual `Frontend` callbacks.
+///
+/// Each deal is made of steps: first the auction, then each trick.
+pub struct ReplayViewer {
+    deals: Vec<DealRecord>,
+    // The frontend sees the game from this seat.
+    seat: pos::PlayerPos,
+
+    // Current deal
+    deal: usize,
+    // Number of tricks shown in the current deal (0 is the auction)
+    step: usize,
+}
+
+impl ReplayViewer {
+    /// Checks the replay, and starts at the auction of its first deal.
+    pub fn new(replay: &Replay, seat: pos::PlayerPos) -> Result<Self, ReplayError> {
+        let deals = try!(replay.check());
+        Ok(ReplayViewer {
+            deals: deals,
+            seat: seat,
+            deal: 0,
+            step: 0,
+        })
+    }
+
+    /// Returns the finished deals of the replay.
+    pub fn deals(&self) -> &[DealRecord] {
+        &self.deals
+    }
+
+    /// Returns the current deal, and the number of tricks shown in it.
+    pub fn position(&self) -> (usize, usize) {
+        (self.deal, self.step)
+    }
+
+    // Number of steps after the auction in the current deal.
+    fn last_step(&self) -> usize {
+        self.deals[self.deal].tricks.len()
+    }
+
+    /// Returns the hand of each player at the current step.
+    pub fn hands(&self) -> [cards::Hand; 4] {
+        hands_before(&self.deals[self.deal], self.step)
+    }
+
+    /// Moves to the next step, and shows it.
+    ///
+    /// Returns `false` if the replay is over.
+    pub fn forward<B: Backend, F: Frontend<B>>(&mut self, frontend: &mut F) -> bool {
+        if self.deals.is_empty() {
+            return false;
+        }
+
+        if self.step < self.last_step() {
+            self.step += 1;
+            self.show_trick::<B, F>(frontend);
+        } else if self.deal + 1 < self.deals.len() {
+            self.deal += 1;
+            self.step = 0;
+            self.show::<B, F>(frontend);
+        } else {
+            return false;
+        }
+
+        true
+    }
+
+    /// Moves to the previous step, and shows it again.
+    ///
+    /// Returns `false` if we are already at the start.
+    pub fn backward<B: Backend, F: Frontend<B>>(&mut self, frontend: &mut F) -> bool {
+        if self.step > 0 {
+            self.step -= 1;
+        } else if self.deal > 0 {
+            self.deal -= 1;
+            self.step = self.last_step();
+        } else {
+            return false;
+        }
+
+        self.show::<B, F>(frontend);
+        true
+    }
+
+    /// Shows the current step from the start of its deal.
+    ///
+    /// The frontend is given the hand it had at this point.
+    pub fn show<B: Backend, F: Frontend<B>>(&self, frontend: &mut F) {
+        let deal = match self.deals.get(self.deal) {
+            Some(deal) => deal,
+            None => return,
+        };
+
+        if self.step == 0 {
+            frontend.start_game(deal.first, deal.hands[self.seat as usize]);
+            for action in &deal.auction {
+                match action.event {
+                    PlayerEvent::Bidded(suit, target) => {
+                        frontend.show_bid(action.pos, suit, target)
+                    }
+                    PlayerEvent::Passed => frontend.show_pass(action.pos),
+                    PlayerEvent::Coinched => frontend.show_coinche(action.pos),
+                    PlayerEvent::CardPlayed(_) => (),
+                }
+            }
+            match deal.contract {
+                Some(ref contract) => frontend.auction_over(contract),
+                None => frontend.auction_cancelled(),
+            }
+        } else {
+            // Catch up with the cards already played.
+            let hand = hands_before(deal, self.step - 1)[self.seat as usize];
+            frontend.start_game(deal.first, hand);
+            if let Some(ref contract) = deal.contract {
+                frontend.auction_over(contract);
+            }
+            self.show_trick::<B, F>(frontend);
+        }
+    }
+
+    // Shows the last trick of the current step.
+    fn show_trick<B: Backend, F: Frontend<B>>(&self, frontend: &mut F) {
+        let deal = &self.deals[self.deal];
+        let trick = &deal.tricks[self.step - 1];
+
+        let mut pos = trick.first;
+        for &card in &trick.cards {
+            frontend.show_card_played(pos, card);
+            pos = pos.next();
+        }
+        frontend.show_trick_over(trick.winner);
+
+        if self.step == self.last_step() {
+            if let Some(winner) = deal.winner {
+                frontend.game_over(deal.points, winner, deal.scores);
+            }
+        }
+    }
+}
+
+// Returns the hands before the given trick.
+fn hands_before(deal: &DealRecord, trick_count: usize) -> [cards::Hand; 4] {
+    let mut hands = deal.hands;
+    for trick in &deal.tricks[..trick_count] {
+        let mut pos = trick.first;
+        for &card in &trick.cards {
+            hands[pos as usize].remove(card);
+            pos = pos.next();
+        }
+    }
+    hands
+}
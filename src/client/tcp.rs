@@ -0,0 +1,252 @@
+use std::io::{self, BufRead, BufReader, Write};
+use std::collections::VecDeque;
+use std::net::{TcpStream, ToSocketAddrs};
+use rustc_serialize::{json, Decodable};
+use libcoinche::pos;
+
+use {NewPartyInfo, ResumeInfo, JoinBody, TcpRequest, TcpMessage, Event, EventType, ContractBody,
+     CardBody};
+
+use super::Backend;
+
+/// TCP coinched client.
+///
+/// Keeps a single connection to the server, which pushes the events.
+pub struct TcpBackend {
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
+
+    pub pos: pos::PlayerPos,
+    /// Name of the player on each seat.
+    pub names: Vec<String>,
+
+    // ID of the next request.
+    request_id: u32,
+    // ID of the next event to return.
+    event_id: usize,
+    // Events received but not consumed yet.
+    pending: VecDeque<Event>,
+}
+
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    Json(json::DecoderError),
+    Coinched(::Error),
+    /// The server closed the connection.
+    Closed,
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Self {
+        Error::Io(err)
+    }
+}
+
+impl From<json::DecoderError> for Error {
+    fn from(err: json::DecoderError) -> Self {
+        Error::Json(err)
+    }
+}
+
+impl TcpBackend {
+    fn connect<A: ToSocketAddrs>(address: A) -> Result<(BufReader<TcpStream>, TcpStream), Error> {
+        let stream = try!(TcpStream::connect(address));
+        let writer = try!(stream.try_clone());
+        Ok((BufReader::new(stream), writer))
+    }
+
+    fn new(reader: BufReader<TcpStream>,
+           writer: TcpStream,
+           party: NewPartyInfo,
+           event_id: usize)
+           -> Self {
+        TcpBackend {
+            reader: reader,
+            writer: writer,
+            pos: party.player_pos,
+            names: party.names,
+            // The join or resume request used the first ID.
+            request_id: 1,
+            event_id: event_id,
+            pending: VecDeque::new(),
+        }
+    }
+
+    /// Attempt to join a game on the given address, like `localhost:3003`.
+    ///
+    /// If `login` is a token from `HttpBackend::login`, the seat is tied to that account.
+    pub fn join<A: ToSocketAddrs>(address: A,
+                                  options: &JoinBody,
+                                  login: Option<&str>)
+                                  -> Result<Self, Error> {
+        let (mut reader, mut writer) = try!(TcpBackend::connect(address));
+        let request = TcpRequest {
+            id: 0,
+            action: "join".to_string(),
+            join: Some(options.clone()),
+            token: login.map(|token| token.to_string()),
+            ..Default::default()
+        };
+        let party: NewPartyInfo = try!(request_on(&mut reader, &mut writer, &request, None));
+
+        Ok(TcpBackend::new(reader, writer, party, 0))
+    }
+
+    /// Attempt to get back in a party we were already playing.
+    ///
+    /// The next events will replay the current deal from its start.
+    pub fn resume<A: ToSocketAddrs>(address: A,
+                                    player_id: u32,
+                                    token: &str)
+                                    -> Result<(Self, ResumeInfo), Error> {
+        let (mut reader, mut writer) = try!(TcpBackend::connect(address));
+        let request = TcpRequest {
+            id: 0,
+            action: "resume".to_string(),
+            player_id: Some(player_id),
+            token: Some(token.to_string()),
+            ..Default::default()
+        };
+        let info: ResumeInfo = try!(request_on(&mut reader, &mut writer, &request, None));
+
+        let backend = TcpBackend::new(reader, writer, info.party.clone(), info.deal_event_id);
+        Ok((backend, info))
+    }
+
+    // Sends a request, and returns its result.
+    //
+    // Events pushed in the meantime are kept for later.
+    fn request<T: Decodable>(&mut self, mut request: TcpRequest) -> Result<T, Error> {
+        request.id = self.request_id;
+        self.request_id += 1;
+        request_on(&mut self.reader,
+                   &mut self.writer,
+                   &request,
+                   Some(&mut self.pending))
+    }
+
+    // Sends an action, and returns the event it caused.
+    fn act(&mut self, request: TcpRequest) -> Result<EventType, Error> {
+        match self.request::<Event>(request) {
+            Ok(event) => {
+                // It will also be pushed with the others: skip it then.
+                if event.id >= self.event_id {
+                    self.event_id = event.id + 1;
+                }
+                Ok(event.event)
+            }
+            Err(err) => {
+                // The action failed: it's still our turn.
+                self.pending.push_back(Event {
+                    event: EventType::YourTurn,
+                    id: self.event_id,
+                });
+                Err(err)
+            }
+        }
+    }
+}
+
+// Reads the next message from the server.
+fn read_message(reader: &mut BufReader<TcpStream>) -> Result<TcpMessage, Error> {
+    let mut line = String::new();
+    if try!(reader.read_line(&mut line)) == 0 {
+        return Err(Error::Closed);
+    }
+    Ok(try!(json::decode(&line)))
+}
+
+// Sends a request and waits for its response.
+//
+// Pushed events are kept in `pending`, if given.
+fn request_on<T: Decodable>(reader: &mut BufReader<TcpStream>,
+                            writer: &mut TcpStream,
+                            request: &TcpRequest,
+                            mut pending: Option<&mut VecDeque<Event>>)
+                            -> Result<T, Error> {
+    try!(writeln!(writer, "{}", json::encode(request).unwrap()));
+    try!(writer.flush());
+
+    loop {
+        let message = try!(read_message(reader));
+        if message.id != Some(request.id) {
+            if let (Some(event), Some(pending)) = (message.event, pending.as_mut()) {
+                pending.push_back(event);
+            }
+            continue;
+        }
+
+        if let Some(err) = message.error {
+            return Err(Error::Coinched(err));
+        }
+        let result = message.result.unwrap_or(json::Json::Null);
+        let mut decoder = json::Decoder::new(result);
+        return Ok(try!(Decodable::decode(&mut decoder)));
+    }
+}
+
+impl Backend for TcpBackend {
+    type Error = Error;
+
+    fn wait(&mut self) -> Result<EventType, Error> {
+        loop {
+            let event = match self.pending.pop_front() {
+                Some(event) => event,
+                None => {
+                    let message = try!(read_message(&mut self.reader));
+                    if let Some(err) = message.error {
+                        return Err(Error::Coinched(err));
+                    }
+                    match message.event {
+                        Some(event) => event,
+                        None => continue,
+                    }
+                }
+            };
+
+            match event.event {
+                EventType::YourTurn => return Ok(event.event),
+                // Already returned by an action.
+                _ if event.id < self.event_id => continue,
+                _ => {
+                    self.event_id = event.id + 1;
+                    return Ok(event.event);
+                }
+            }
+        }
+    }
+
+    fn bid(&mut self, contract: ContractBody) -> Result<EventType, Error> {
+        self.act(TcpRequest {
+            action: "bid".to_string(),
+            target: Some(contract.target),
+            suit: Some(contract.suit),
+            ..Default::default()
+        })
+    }
+
+    fn pass(&mut self) -> Result<EventType, Error> {
+        self.act(TcpRequest { action: "pass".to_string(), ..Default::default() })
+    }
+
+    fn coinche(&mut self) -> Result<EventType, Error> {
+        self.act(TcpRequest { action: "coinche".to_string(), ..Default::default() })
+    }
+
+    fn play_card(&mut self, card: CardBody) -> Result<EventType, Error> {
+        self.act(TcpRequest {
+            action: "play".to_string(),
+            card: Some(card.card),
+            ..Default::default()
+        })
+    }
+
+    fn leave(&mut self) -> Result<(), Error> {
+        let _: String = try!(self.request(TcpRequest {
+            action: "leave".to_string(),
+            ..Default::default()
+        }));
+        Ok(())
+    }
+}
@@ -0,0 +1,201 @@
+use std::collections::VecDeque;
+use rustc_serialize::{json, Decodable, Encodable};
+use libcoinche::pos;
+use websocket;
+use websocket::{Message, Sender as SenderTrait, Receiver as ReceiverTrait};
+use websocket::client::request::Url;
+use websocket::message::Type;
+use websocket::stream::WebSocketStream;
+
+use {ConnectBody, ActionBody, Event, EventType, ContractBody, CardBody};
+
+use super::Backend;
+
+type Sender = websocket::client::Sender<WebSocketStream>;
+type Receiver = websocket::client::Receiver<WebSocketStream>;
+
+/// WebSocket coinched client.
+///
+/// Events are pushed by the server instead of being polled.
+/// It needs a seat first, taken with an `HttpBackend`.
+pub struct WsBackend {
+    sender: Sender,
+    receiver: Receiver,
+    pos: pos::PlayerPos,
+
+    // Events received but not consumed yet.
+    pending: VecDeque<EventType>,
+}
+
+#[derive(Debug)]
+pub enum Error {
+    Url(String),
+    WebSocket(websocket::result::WebSocketError),
+    Json(json::DecoderError),
+    Coinched(::Error),
+    /// The server closed the connection.
+    Closed,
+}
+
+impl From<websocket::result::WebSocketError> for Error {
+    fn from(err: websocket::result::WebSocketError) -> Self {
+        Error::WebSocket(err)
+    }
+}
+
+impl From<json::ParserError> for Error {
+    fn from(err: json::ParserError) -> Self {
+        Error::Json(json::DecoderError::ParseError(err))
+    }
+}
+
+impl From<json::DecoderError> for Error {
+    fn from(err: json::DecoderError) -> Self {
+        Error::Json(err)
+    }
+}
+
+// A message from the server.
+enum Incoming {
+    Event(Event),
+    Error(::Error),
+}
+
+impl WsBackend {
+    /// Connects to the WebSocket interface of a server, like `ws://localhost:3001`.
+    ///
+    /// Takes the seat owned by `token`, and receives events starting at `event_id`.
+    pub fn connect(url: &str,
+                   player_id: u32,
+                   token: &str,
+                   event_id: usize,
+                   pos: pos::PlayerPos)
+                   -> Result<Self, Error> {
+        let url = try!(Url::parse(url).map_err(|err| Error::Url(err.to_string())));
+        let request = try!(websocket::Client::connect(url));
+        let response = try!(request.send());
+        try!(response.validate());
+        let (sender, receiver) = response.begin().split();
+
+        let mut backend = WsBackend {
+            sender: sender,
+            receiver: receiver,
+            pos: pos,
+            pending: VecDeque::new(),
+        };
+        try!(backend.send(&ConnectBody {
+            player_id: player_id,
+            token: token.to_string(),
+            event_id: event_id,
+        }));
+
+        Ok(backend)
+    }
+
+    fn send<T: Encodable>(&mut self, value: &T) -> Result<(), Error> {
+        let message = Message::text(json::encode(value).unwrap());
+        try!(self.sender.send_message(&message));
+        Ok(())
+    }
+
+    // Reads the next event or error sent by the server.
+    fn receive(&mut self) -> Result<Incoming, Error> {
+        loop {
+            let message: Message = try!(self.receiver.recv_message());
+            match message.opcode {
+                Type::Text => {
+                    let text = String::from_utf8_lossy(&message.payload).into_owned();
+                    let json = try!(json::Json::from_str(&text));
+                    let is_error = json.as_object()
+                                       .map(|obj| obj.contains_key("error"))
+                                       .unwrap_or(false);
+                    let mut decoder = json::Decoder::new(json);
+                    return if is_error {
+                        Ok(Incoming::Error(try!(Decodable::decode(&mut decoder))))
+                    } else {
+                        Ok(Incoming::Event(try!(Decodable::decode(&mut decoder))))
+                    };
+                }
+                Type::Ping => {
+                    try!(self.sender.send_message(&Message::pong(message.payload)));
+                }
+                Type::Close => return Err(Error::Closed),
+                _ => (),
+            }
+        }
+    }
+
+    // Sends an action, and returns the event it caused.
+    fn act(&mut self, action: ActionBody) -> Result<EventType, Error> {
+        try!(self.send(&action));
+
+        // Our event comes on the stream, maybe after others.
+        let mut others = Vec::new();
+        let result;
+        loop {
+            match try!(self.receive()) {
+                Incoming::Event(Event { event: EventType::FromPlayer(pos, event), .. })
+                    if pos == self.pos => {
+                    result = Ok(EventType::FromPlayer(pos, event));
+                    break;
+                }
+                Incoming::Event(event) => others.push(event.event),
+                Incoming::Error(err) => {
+                    // The action failed: it's still our turn.
+                    others.push(EventType::YourTurn);
+                    result = Err(Error::Coinched(err));
+                    break;
+                }
+            }
+        }
+        self.pending.extend(others);
+
+        result
+    }
+}
+
+impl Backend for WsBackend {
+    type Error = Error;
+
+    fn wait(&mut self) -> Result<EventType, Error> {
+        if let Some(event) = self.pending.pop_front() {
+            return Ok(event);
+        }
+
+        match try!(self.receive()) {
+            Incoming::Event(event) => Ok(event.event),
+            Incoming::Error(err) => Err(Error::Coinched(err)),
+        }
+    }
+
+    fn bid(&mut self, contract: ContractBody) -> Result<EventType, Error> {
+        self.act(ActionBody {
+            action: "bid".to_string(),
+            target: Some(contract.target),
+            suit: Some(contract.suit),
+            ..Default::default()
+        })
+    }
+
+    fn pass(&mut self) -> Result<EventType, Error> {
+        self.act(ActionBody { action: "pass".to_string(), ..Default::default() })
+    }
+
+    fn coinche(&mut self) -> Result<EventType, Error> {
+        self.act(ActionBody { action: "coinche".to_string(), ..Default::default() })
+    }
+
+    fn play_card(&mut self, card: CardBody) -> Result<EventType, Error> {
+        self.act(ActionBody {
+            action: "play".to_string(),
+            card: Some(card.card),
+            ..Default::default()
+        })
+    }
+
+    fn leave(&mut self) -> Result<(), Error> {
+        try!(self.send(&ActionBody { action: "leave".to_string(), ..Default::default() }));
+        self.sender.send_message(&Message::close()).ok();
+        Ok(())
+    }
+}
@@ -92,7 +92,7 @@ pub enum EventType {
     },
 
     /// New game: contains the first player, and the player's hand.
-    /// For internal use only, it is never sent on the network.
+    /// For internal use only, it is never sent on the network (but it is saved on disk).
     NewGame {
         first: pos::PlayerPos,
         hands: [cards::Hand; 4],
@@ -102,6 +102,12 @@ pub enum EventType {
         first: pos::PlayerPos,
         hand: cards::Hand,
     },
+    /// New game event, translated for spectators.
+    /// Hands are only given if spectators see the game with a delay.
+    NewGameSpectated {
+        first: pos::PlayerPos,
+        hands: Option<[cards::Hand; 4]>,
+    },
 
     /// Game over: contains scores
     GameOver {
@@ -126,6 +132,25 @@ impl EventType {
             _ => self.clone(),
         }
     }
+
+    /// Returns a version of the event for spectators.
+    ///
+    /// Like `relativize`, but a NewGame only shows the hands if `reveal` is `true`.
+    pub fn spectate(&self, reveal: bool) -> Self {
+        match self {
+            &EventType::NewGame { first, hands } => {
+                EventType::NewGameSpectated {
+                    first: first,
+                    hands: if reveal {
+                        Some(hands)
+                    } else {
+                        None
+                    },
+                }
+            }
+            _ => self.clone(),
+        }
+    }
 }
 
 // Ugly serialization...
@@ -184,6 +209,14 @@ impl rustc_serialize::Encodable for EventType {
                     Ok(())
                 })
             }
+            &EventType::NewGameSpectated { first, ref hands } => {
+                s.emit_struct("Event", 3, |s| {
+                    try!(encode_field!(s, "type", 0, "NewGameSpectated"));
+                    try!(encode_field!(s, "first", 1, first));
+                    try!(encode_field!(s, "hands", 2, hands));
+                    Ok(())
+                })
+            }
             &EventType::GameOver { points, winner, scores } => {
                 s.emit_struct("Event", 4, |s| {
                     try!(encode_field!(s, "type", 0, "GameOver"));
@@ -230,6 +263,22 @@ impl rustc_serialize::Decodable for EventType {
                         scores: scores,
                     })
                 }
+                "NewGameGlobal" => {
+                    let first = try!(decode_field!(d, "first", 1));
+                    let hands = try!(decode_field!(d, "hands", 2));
+                    Ok(EventType::NewGame {
+                        first: first,
+                        hands: hands,
+                    })
+                }
+                "NewGameSpectated" => {
+                    let first = try!(decode_field!(d, "first", 1));
+                    let hands = try!(decode_field!(d, "hands", 2));
+                    Ok(EventType::NewGameSpectated {
+                        first: first,
+                        hands: hands,
+                    })
+                }
                 "NewGame" => {
                     let first = try!(decode_field!(d, "first", 1));
                     let cards = try!(decode_field!(d, "cards", 2));
@@ -8,6 +8,9 @@ extern crate iron;
 extern crate url;
 extern crate hyper;
 extern crate bodyparser;
+extern crate crypto;
+extern crate websocket;
+extern crate num_cpus;
 
 #[macro_use]
 extern crate log;
@@ -26,6 +29,8 @@ macro_rules! encode_field {
 }
 
 mod event;
+pub mod ai;
+pub mod replay;
 pub mod client;
 pub mod server;
 
@@ -40,16 +45,230 @@ pub struct NewPartyInfo {
     pub player_id: u32,
     /// Player position in the table.
     pub player_pos: libcoinche::pos::PlayerPos,
+    /// Name of the player on each seat.
+    pub names: Vec<String>,
+    /// Secret token proving the player owns the seat.
+    ///
+    /// Send it in the `Authorization` header of every request, as `Bearer <token>`.
+    pub token: String,
+}
+
+/// Player logged in.
+#[derive(Clone,Debug,RustcEncodable,RustcDecodable)]
+pub struct LoginInfo {
+    /// Login token. Send it in the `Authorization` header when joining a party.
+    pub token: String,
+}
+
+/// A private table was created.
+#[derive(Clone,Debug,RustcEncodable,RustcDecodable)]
+pub struct TableInfo {
+    /// Code to give to the other players, so they can join the table.
+    pub code: String,
+}
+
+/// Player resumed an existing party.
+#[derive(Clone,Debug,RustcEncodable,RustcDecodable)]
+pub struct ResumeInfo {
+    /// Same information as when the party was joined.
+    pub party: NewPartyInfo,
+    /// Cards currently in hand.
+    pub hand: libcoinche::cards::Hand,
+    /// ID of the latest event in the party.
+    pub last_event_id: usize,
+    /// ID of the NewGame event that started the current deal.
+    ///
+    /// Waiting from there replays the deal so far.
+    pub deal_event_id: usize,
+}
+
+/// A party going on, as listed for spectators.
+#[derive(Clone,Debug,RustcEncodable,RustcDecodable)]
+pub struct PartySummary {
+    /// Party ID, used to spectate it.
+    pub party_id: u32,
+    /// Name of the player on each seat.
+    pub names: Vec<String>,
+    /// Current scores of both teams.
+    pub scores: [i32; 2],
+    /// Number of deals already played.
+    pub deals: usize,
+}
+
+/// Spectator just started watching a party.
+#[derive(Clone,Debug,RustcEncodable,RustcDecodable)]
+pub struct SpectatorInfo {
+    /// Spectator ID, used to follow the party events.
+    pub spectator_id: u32,
+    /// Name of the player on each seat.
+    pub names: Vec<String>,
+    /// ID of the NewGame event that started the current deal.
+    ///
+    /// Waiting from there shows the deal so far.
+    pub deal_event_id: usize,
+    /// Delay (in milliseconds) after which spectators see the events.
+    ///
+    /// Hands are only shown when there is a delay.
+    pub delay: Option<u32>,
+}
+
+/// An action from a player during an auction.
+#[derive(Clone,Debug,RustcEncodable,RustcDecodable)]
+pub struct AuctionRecord {
+    /// Player who acted.
+    pub pos: libcoinche::pos::PlayerPos,
+    /// What he did: a bid, a coinche or a pass.
+    pub event: PlayerEvent,
+}
+
+/// A complete trick from a past deal.
+#[derive(Clone,Debug,RustcEncodable,RustcDecodable)]
+pub struct TrickRecord {
+    /// Player who started the trick.
+    pub first: libcoinche::pos::PlayerPos,
+    /// Cards, in the order they were played.
+    pub cards: Vec<libcoinche::cards::Card>,
+    /// Player who won the trick.
+    pub winner: libcoinche::pos::PlayerPos,
+}
+
+/// A finished deal.
+#[derive(Clone,Debug,RustcEncodable,RustcDecodable)]
+pub struct DealRecord {
+    /// First player of the auction.
+    pub first: libcoinche::pos::PlayerPos,
+    /// Hand of each player at the start of the deal.
+    pub hands: [libcoinche::cards::Hand; 4],
+    /// Every action during the auction.
+    pub auction: Vec<AuctionRecord>,
+    /// Final contract. `None` if the auction was cancelled.
+    pub contract: Option<libcoinche::bid::Contract>,
+    /// Every trick played.
+    pub tricks: Vec<TrickRecord>,
+    /// Team who won the deal. `None` if the auction was cancelled.
+    pub winner: Option<libcoinche::pos::Team>,
+    /// Points made by each team.
+    pub points: [i32; 2],
+    /// Score earned by each team.
+    pub scores: [i32; 2],
 }
 
 #[derive(Clone,Debug,RustcEncodable,RustcDecodable)]
 pub struct Error {
+    /// Human-readable message.
     pub error: String,
+    /// Error identifier, when it comes from the game manager.
+    pub code: Option<String>,
+}
+
+/// A message from the TCP interface, on a single line.
+///
+/// It is either the response to a request, or an event pushed to the player.
+#[derive(Clone,Debug,RustcDecodable,RustcEncodable)]
+pub struct TcpMessage {
+    /// ID of the request it responds to.
+    pub id: Option<u32>,
+    /// Result of the request, if it succeeded.
+    pub result: Option<rustc_serialize::json::Json>,
+    /// Error, if the request failed.
+    pub error: Option<Error>,
+    /// Event pushed to the player.
+    pub event: Option<Event>,
+}
+
+
+// Structures written by the server for its admin
+
+/// A seat of a party, as seen by the admin.
+#[derive(Clone,Debug,RustcEncodable,RustcDecodable)]
+pub struct SeatStatus {
+    /// Position of the seat.
+    pub pos: libcoinche::pos::PlayerPos,
+    /// Name of the player on this seat.
+    pub name: String,
+    /// Is this seat taken by a bot?
+    pub bot: bool,
+    /// Player ID of the human on this seat, unless he left.
+    pub player_id: Option<u32>,
+    /// Account of the human on this seat, if he logged in.
+    pub account: Option<String>,
+    /// Seconds since the last request from this player.
+    pub idle: Option<i64>,
+}
+
+/// A party, as seen by the admin.
+#[derive(Clone,Debug,RustcEncodable,RustcDecodable)]
+pub struct PartyStatus {
+    pub party_id: u32,
+    /// `Bidding`, `Playing` or `Cancelled`.
+    pub phase: String,
+    /// Current scores of both teams.
+    pub scores: [i32; 2],
+    /// Number of deals already played.
+    pub deals: usize,
+    /// Number of events so far.
+    pub events: usize,
+    pub seats: Vec<SeatStatus>,
+}
+
+/// Overview of the server, for the admin.
+#[derive(Clone,Debug,RustcEncodable,RustcDecodable)]
+pub struct ServerStatus {
+    /// Players waiting in the public queue.
+    pub waiting_list: usize,
+    /// Private tables waiting for their players.
+    pub tables: usize,
+    /// Parties going on.
+    pub parties: usize,
+    /// Players currently in a party.
+    pub players: usize,
+    pub spectators: usize,
 }
 
 
 // Structures written by the client, read by the server.
 
+/// Name and password of a registered player.
+#[derive(Clone,Debug,RustcDecodable,RustcEncodable)]
+pub struct AccountBody {
+    pub name: String,
+    pub password: String,
+}
+
+/// Options when joining a party.
+#[derive(Clone,Debug,Default,RustcDecodable,RustcEncodable)]
+pub struct JoinBody {
+    /// Name to display to the other players.
+    ///
+    /// Ignored for logged in players, who use their account name.
+    pub name: Option<String>,
+    /// If set, empty seats are given to bots after this many seconds.
+    ///
+    /// It replaces the join timeout. With `0`, the party starts right away.
+    pub bots_after: Option<u32>,
+}
+
+/// Options when joining a private table.
+///
+/// Without a seat or a team, the player takes the first free seat.
+#[derive(Clone,Debug,Default,RustcDecodable,RustcEncodable)]
+pub struct TableJoinBody {
+    /// Name to display to the other players.
+    ///
+    /// Ignored for logged in players, who use their account name.
+    pub name: Option<String>,
+    /// Seat to take.
+    pub seat: Option<libcoinche::pos::PlayerPos>,
+    /// Team to join, if no seat is given.
+    pub team: Option<libcoinche::pos::Team>,
+}
+
+/// Reason given by the admin when cancelling a party.
+#[derive(Clone,Debug,Default,RustcDecodable,RustcEncodable)]
+pub struct CancelBody {
+    /// Message sent to the players.
+    pub msg: Option<String>,
+}
 
 #[derive(Clone,Debug,RustcDecodable,RustcEncodable)]
 pub struct ContractBody {
@@ -60,4 +279,47 @@ pub struct ContractBody {
 #[derive(Clone,Debug,RustcDecodable,RustcEncodable)]
 pub struct CardBody {
     pub card: libcoinche::cards::Card,
-}
\ No newline at end of file
+}
+
+/// First message on a WebSocket, to take a seat.
+#[derive(Clone,Debug,RustcDecodable,RustcEncodable)]
+pub struct ConnectBody {
+    pub player_id: u32,
+    /// Token owning the seat.
+    pub token: String,
+    /// ID of the first event to send.
+    pub event_id: usize,
+}
+
+/// An action sent on a WebSocket.
+#[derive(Clone,Debug,Default,RustcDecodable,RustcEncodable)]
+pub struct ActionBody {
+    /// `bid`, `pass`, `coinche`, `play` or `leave`.
+    pub action: String,
+    /// Target of a bid.
+    pub target: Option<libcoinche::bid::Target>,
+    /// Trump of a bid.
+    pub suit: Option<libcoinche::cards::Suit>,
+    /// Card to play.
+    pub card: Option<libcoinche::cards::Card>,
+}
+/// A request on the TCP interface, on a single line.
+#[derive(Clone,Debug,Default,RustcDecodable,RustcEncodable)]
+pub struct TcpRequest {
+    /// Correlation ID, sent back with the response.
+    pub id: u32,
+    /// `join`, `resume`, `bid`, `pass`, `coinche`, `play` or `leave`.
+    pub action: String,
+    /// Options of a `join`.
+    pub join: Option<JoinBody>,
+    /// Seat to `resume`.
+    pub player_id: Option<u32>,
+    /// Token owning the seat to `resume`, or login token for a `join`.
+    pub token: Option<String>,
+    /// Target of a bid.
+    pub target: Option<libcoinche::bid::Target>,
+    /// Trump of a bid.
+    pub suit: Option<libcoinche::cards::Suit>,
+    /// Card to play.
+    pub card: Option<libcoinche::cards::Card>,
+}
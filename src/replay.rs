@@ -0,0 +1,376 @@
+//! Replay files, to keep a whole party once it's over.
+//!
+//! A replay is a JSON document holding the seats and every event of the party,
+//! with the time it happened. See `replay.md` for the format.
+
+use std::fmt;
+
+use rustc_serialize::json;
+use time;
+
+use libcoinche::{bid, cards, game, pos};
+use {EventType, PlayerEvent, AuctionRecord, TrickRecord, DealRecord};
+
+/// Version of the replay format written by this crate.
+pub const FORMAT_VERSION: u32 = 1;
+
+// Timestamps are written in UTC, as RFC 3339.
+const TIME_FORMAT: &'static str = "%Y-%m-%dT%H:%M:%SZ";
+
+/// An event, with the time it happened.
+#[derive(Clone,Debug,RustcEncodable,RustcDecodable)]
+pub struct TimedEvent {
+    /// When the event happened, like `2016-01-31T20:15:00Z`.
+    pub time: String,
+    /// The event itself. New deals include every hand.
+    pub event: EventType,
+}
+
+impl TimedEvent {
+    pub fn new(event: EventType, time: &time::Tm) -> Self {
+        TimedEvent {
+            time: time::strftime(TIME_FORMAT, &time.to_utc()).unwrap(),
+            event: event,
+        }
+    }
+
+    /// Parses the event time. Returns `None` if it is invalid.
+    pub fn tm(&self) -> Option<time::Tm> {
+        time::strptime(&self.time, TIME_FORMAT).ok()
+    }
+}
+
+/// A complete party.
+#[derive(Clone,Debug,RustcEncodable,RustcDecodable)]
+pub struct Replay {
+    /// Format version, currently `FORMAT_VERSION`.
+    pub version: u32,
+    /// Name of each seat.
+    pub names: Vec<String>,
+    /// Seats taken by bots.
+    pub bots: [bool; 4],
+    /// Every event of the party, in order.
+    pub events: Vec<TimedEvent>,
+}
+
+/// Error found when importing a replay.
+#[derive(Debug)]
+pub enum ReplayError {
+    /// The file is not a valid replay document.
+    Json(json::DecoderError),
+    /// The replay was written with an unknown format version.
+    Version(u32),
+    /// The event with the given ID breaks the rules of the game.
+    Invalid(usize, String),
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            &ReplayError::Json(ref err) => write!(f, "invalid replay file: {}", err),
+            &ReplayError::Version(version) => write!(f, "unknown replay version: {}", version),
+            &ReplayError::Invalid(id, ref msg) => write!(f, "invalid event {}: {}", id, msg),
+        }
+    }
+}
+
+impl From<json::DecoderError> for ReplayError {
+    fn from(err: json::DecoderError) -> Self {
+        ReplayError::Json(err)
+    }
+}
+
+/// Sums up a deal from its events, starting with its NewGame event.
+///
+/// Returns `None` if the events don't start with a NewGame.
+pub fn make_deal_record(events: &[EventType]) -> Option<DealRecord> {
+    let mut record = match events.first() {
+        Some(&EventType::NewGame { first, hands }) => {
+            DealRecord {
+                first: first,
+                hands: hands,
+                auction: Vec::new(),
+                contract: None,
+                tricks: Vec::new(),
+                winner: None,
+                points: [0; 2],
+                scores: [0; 2],
+            }
+        }
+        _ => return None,
+    };
+
+    let mut trick = Vec::new();
+    let mut trick_first = None;
+    for event in &events[1..] {
+        match event {
+            &EventType::FromPlayer(pos, PlayerEvent::CardPlayed(card)) => {
+                if trick_first.is_none() {
+                    trick_first = Some(pos);
+                }
+                trick.push(card);
+            }
+            &EventType::FromPlayer(pos, ref event) => {
+                record.auction.push(AuctionRecord {
+                    pos: pos,
+                    event: event.clone(),
+                })
+            }
+            &EventType::BidOver(ref contract) => record.contract = Some(contract.clone()),
+            &EventType::TrickOver { winner } => {
+                record.tricks.push(TrickRecord {
+                    first: trick_first.take().unwrap_or(winner),
+                    cards: trick.drain(..).collect(),
+                    winner: winner,
+                })
+            }
+            &EventType::GameOver { points, winner, scores } => {
+                record.winner = Some(winner);
+                record.points = points;
+                record.scores = scores;
+            }
+            _ => (),
+        }
+    }
+
+    Some(record)
+}
+
+// Where we are in the party, while checking a replay.
+enum State {
+    // Between two deals
+    Idle,
+    Bidding(pos::PlayerPos, [cards::Hand; 4], bid::Auction),
+    Playing(game::GameState),
+    // The party was cancelled: nothing can happen anymore.
+    Cancelled,
+}
+
+// Checks that the hands are a proper deal: 8 cards each, all different.
+fn check_hands(hands: &[cards::Hand; 4]) -> Result<(), String> {
+    for (i, hand) in hands.iter().enumerate() {
+        if hand.size() != 8 {
+            return Err(format!("hand {} has {} cards", i, hand.size()));
+        }
+        for other in &hands[i + 1..] {
+            if hand.list().into_iter().any(|card| other.has(card)) {
+                return Err(format!("hand {} shares cards with another hand", i));
+            }
+        }
+    }
+    Ok(())
+}
+
+// Compares an event from the replay with the one the rules produce.
+fn same_outcome(expected: &EventType, actual: &EventType) -> bool {
+    match (expected, actual) {
+        (&EventType::BidOver(ref a), &EventType::BidOver(ref b)) => {
+            a.author == b.author && a.trump == b.trump && a.target == b.target
+        }
+        (&EventType::BidCancelled, &EventType::BidCancelled) => true,
+        (&EventType::TrickOver { winner: a }, &EventType::TrickOver { winner: b }) => a == b,
+        (&EventType::GameOver { points: p1, scores: s1, .. },
+         &EventType::GameOver { points: p2, scores: s2, .. }) => p1 == p2 && s1 == s2,
+        _ => false,
+    }
+}
+
+impl Replay {
+    /// Reads a replay from its JSON representation.
+    ///
+    /// This only checks the format; use `check` to validate the party itself.
+    pub fn from_json(json: &str) -> Result<Self, ReplayError> {
+        let replay: Replay = try!(json::decode(json));
+        if replay.version != FORMAT_VERSION {
+            return Err(ReplayError::Version(replay.version));
+        }
+        Ok(replay)
+    }
+
+    /// Returns the JSON representation of the replay.
+    pub fn to_json(&self) -> String {
+        json::as_pretty_json(self).to_string()
+    }
+
+    /// Plays the party again with the rules of the game.
+    ///
+    /// Every auction, contract, trick and score must match what the rules give.
+    /// Returns the finished deals.
+    pub fn check(&self) -> Result<Vec<DealRecord>, ReplayError> {
+        let events: Vec<EventType> = self.events.iter().map(|e| e.event.clone()).collect();
+
+        let mut deals = Vec::new();
+        let mut state = State::Idle;
+        let mut deal_start = 0;
+
+        let mut i = 0;
+        while i < events.len() {
+            let id = i;
+            let invalid = move |msg: &str| ReplayError::Invalid(id, msg.to_string());
+
+            // Events the rules produce right after this one.
+            let mut outcome = Vec::new();
+            let mut deal_over = false;
+
+            state = match (state, events[i].clone()) {
+                (State::Cancelled, _) => return Err(invalid("party already cancelled")),
+                (_, EventType::PartyCancelled(_)) => State::Cancelled,
+                (State::Idle, EventType::NewGame { first, hands }) => {
+                    try!(check_hands(&hands).map_err(|msg| invalid(&msg)));
+                    deal_start = i;
+                    State::Bidding(first, hands, bid::Auction::new(first))
+                }
+                (State::Bidding(first, ..), EventType::BidCancelled) => {
+                    // A restarted server deals again, with the same first player,
+                    // if it stopped during an auction. Nothing else may cut one short.
+                    match events.get(i + 1) {
+                        Some(&EventType::NewGame { first: next, .. }) if next == first => {
+                            State::Idle
+                        }
+                        _ => return Err(invalid("unexpected event")),
+                    }
+                }
+                (State::Bidding(first, hands, mut auction), EventType::FromPlayer(pos, event)) => {
+                    let result = match event {
+                        PlayerEvent::Bidded(trump, target) => auction.bid(pos, trump, target),
+                        PlayerEvent::Passed => auction.pass(pos),
+                        PlayerEvent::Coinched => auction.coinche(pos),
+                        PlayerEvent::CardPlayed(_) => {
+                            return Err(invalid("card played during auction"))
+                        }
+                    };
+                    match try!(result.map_err(|err| invalid(&err.to_string()))) {
+                        bid::AuctionState::Over => {
+                            // The auction deals its own hands: only keep the contract.
+                            let contract = match auction.complete() {
+                                Ok(game) => game.contract().clone(),
+                                Err(err) => return Err(invalid(&err.to_string())),
+                            };
+                            outcome.push(EventType::BidOver(contract.clone()));
+                            State::Playing(game::GameState::new(first, hands, contract))
+                        }
+                        bid::AuctionState::Cancelled => {
+                            outcome.push(EventType::BidCancelled);
+                            deal_over = true;
+                            State::Idle
+                        }
+                        _ => State::Bidding(first, hands, auction),
+                    }
+                }
+                (State::Playing(mut game),
+                 EventType::FromPlayer(pos, PlayerEvent::CardPlayed(card))) => {
+                    let result = game.play_card(pos, card);
+                    match try!(result.map_err(|err| invalid(&err.to_string()))) {
+                        game::TrickResult::Nothing => State::Playing(game),
+                        game::TrickResult::TrickOver(winner, game::GameResult::Nothing) => {
+                            outcome.push(EventType::TrickOver { winner: winner });
+                            State::Playing(game)
+                        }
+                        game::TrickResult::TrickOver(winner,
+                                                     game::GameResult::GameOver{points,
+                                                                                winners,
+                                                                                scores}) => {
+                            outcome.push(EventType::TrickOver { winner: winner });
+                            outcome.push(EventType::GameOver {
+                                points: points,
+                                winner: winners,
+                                scores: scores,
+                            });
+                            deal_over = true;
+                            State::Idle
+                        }
+                    }
+                }
+                _ => return Err(invalid("unexpected event")),
+            };
+
+            for expected in outcome {
+                i += 1;
+                match events.get(i) {
+                    Some(actual) if same_outcome(&expected, actual) => (),
+                    Some(_) => {
+                        return Err(ReplayError::Invalid(i,
+                                                        format!("expected {:?}", expected)))
+                    }
+                    None => return Err(ReplayError::Invalid(i, "replay is truncated".to_string())),
+                }
+            }
+
+            if deal_over {
+                deals.extend(make_deal_record(&events[deal_start..i + 1]));
+            }
+
+            i += 1;
+        }
+
+        Ok(deals)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use time;
+
+    use libcoinche::{bid, pos};
+    use {EventType, PlayerEvent};
+    use super::{Replay, TimedEvent, FORMAT_VERSION};
+
+    fn make_replay(events: Vec<EventType>) -> Replay {
+        let now = time::now();
+        Replay {
+            version: FORMAT_VERSION,
+            names: vec!["A".to_string(), "B".to_string(), "C".to_string(), "D".to_string()],
+            bots: [false; 4],
+            events: events.into_iter().map(|event| TimedEvent::new(event, &now)).collect(),
+        }
+    }
+
+    fn new_game(first: pos::PlayerPos) -> EventType {
+        EventType::NewGame {
+            first: first,
+            hands: bid::Auction::new(first).hands(),
+        }
+    }
+
+    fn passed(pos: pos::PlayerPos) -> EventType {
+        EventType::FromPlayer(pos, PlayerEvent::Passed)
+    }
+
+    #[test]
+    fn test_redeal_after_restore() {
+        let replay = make_replay(vec![new_game(pos::PlayerPos::P0),
+                                      passed(pos::PlayerPos::P0),
+                                      EventType::BidCancelled,
+                                      new_game(pos::PlayerPos::P0),
+                                      EventType::PartyCancelled("over".to_string())]);
+
+        let deals = replay.check().unwrap();
+        assert!(deals.is_empty());
+    }
+
+    #[test]
+    fn test_bid_cancelled_mid_auction() {
+        // Nothing deals again: the auction goes on after the cancellation.
+        let replay = make_replay(vec![new_game(pos::PlayerPos::P0),
+                                      passed(pos::PlayerPos::P0),
+                                      EventType::BidCancelled,
+                                      passed(pos::PlayerPos::P1)]);
+        assert!(replay.check().is_err());
+
+        // A redeal keeps the same first player.
+        let replay = make_replay(vec![new_game(pos::PlayerPos::P0),
+                                      passed(pos::PlayerPos::P0),
+                                      EventType::BidCancelled,
+                                      new_game(pos::PlayerPos::P1)]);
+        assert!(replay.check().is_err());
+    }
+
+    #[test]
+    fn test_bid_cancelled_between_deals() {
+        let replay = make_replay(vec![new_game(pos::PlayerPos::P0),
+                                      EventType::BidCancelled,
+                                      EventType::BidCancelled]);
+
+        assert!(replay.check().is_err());
+    }
+}
@@ -0,0 +1,85 @@
+//! Registered players.
+
+use std::collections::HashMap;
+
+use crypto::pbkdf2;
+use rand::{OsRng, Rng};
+use rustc_serialize::hex::ToHex;
+
+use super::error::Error;
+
+// Number of PBKDF2 iterations when hashing a password.
+const HASH_ROUNDS: u32 = 10_000;
+
+/// Creates a new random token, too long to be guessed.
+pub fn make_token() -> String {
+    let mut bytes = [0u8; 16];
+    OsRng::new().expect("could not access the system RNG").fill_bytes(&mut bytes);
+    bytes.to_hex()
+}
+
+/// Hashes a password, to register a player.
+///
+/// This is slow on purpose: don't call it with a lock held.
+pub fn hash_password(password: &str) -> String {
+    pbkdf2::pbkdf2_simple(password, HASH_ROUNDS).expect("could not hash the password")
+}
+
+/// Checks a password against the hash returned by `hash_password`.
+///
+/// As slow as `hash_password`.
+pub fn check_password(password: &str, hash: &str) -> bool {
+    pbkdf2::pbkdf2_check(password, hash) == Ok(true)
+}
+
+/// Registered players, and their login tokens.
+pub struct AccountList {
+    // Password hash, by player name
+    accounts: HashMap<String, String>,
+    // Player name, by login token
+    sessions: HashMap<String, String>,
+}
+
+impl AccountList {
+    pub fn new() -> Self {
+        AccountList {
+            accounts: HashMap::new(),
+            sessions: HashMap::new(),
+        }
+    }
+
+    /// Registers a new player, with a hash from `hash_password`.
+    pub fn register(&mut self, name: &str, hash: String) -> Result<(), Error> {
+        if self.accounts.contains_key(name) {
+            return Err(Error::NameTaken);
+        }
+
+        self.accounts.insert(name.to_string(), hash);
+        Ok(())
+    }
+
+    /// Returns the password hash of a player, to check with `check_password`.
+    pub fn hash(&self, name: &str) -> Option<String> {
+        self.accounts.get(name).cloned()
+    }
+
+    /// Returns a new login token for a player whose password was checked.
+    pub fn login(&mut self, name: &str) -> String {
+        let token = make_token();
+        self.sessions.insert(token.clone(), name.to_string());
+        token
+    }
+
+    /// Forgets about a login token.
+    pub fn logout(&mut self, token: &str) -> Result<(), Error> {
+        match self.sessions.remove(token) {
+            Some(_) => Ok(()),
+            None => Err(Error::BadToken),
+        }
+    }
+
+    /// Returns the name of the player using this login token.
+    pub fn user(&self, token: &str) -> Option<&str> {
+        self.sessions.get(token).map(|name| &**name)
+    }
+}
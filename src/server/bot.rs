@@ -0,0 +1,33 @@
+//! Bots, to fill empty seats in a party.
+
+use libcoinche::{bid, cards, pos};
+
+use super::game_manager::Game;
+use ai;
+
+/// Something a bot decided to do.
+pub enum Action {
+    Pass,
+    Bid(cards::Suit, bid::Target),
+    PlayCard(cards::Card),
+}
+
+/// Decides what the bot at `pos` does in the current game.
+///
+/// `opened` tells if someone already bid in the current auction.
+pub fn decide(game: &Game, pos: pos::PlayerPos, opened: bool) -> Action {
+    match game {
+        &Game::Bidding(_, ref hands, _) => {
+            match ai::choose_bid(hands[pos as usize], opened) {
+                Some((suit, target)) => Action::Bid(suit, target),
+                None => Action::Pass,
+            }
+        }
+        &Game::Playing(ref game) => {
+            Action::PlayCard(ai::choose_card(pos,
+                                             game.hands()[pos as usize],
+                                             game.current_trick(),
+                                             game.contract().trump))
+        }
+    }
+}
@@ -0,0 +1,133 @@
+//! Server configuration, read from a JSON file.
+//!
+//! Every field is optional: missing ones keep their default value.
+//! Durations are given in seconds.
+
+use std::fs;
+use std::io::Read;
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::path::Path;
+
+use rustc_serialize::json;
+
+use super::game_manager::Settings;
+
+/// Port used when none is given.
+pub const DEFAULT_PORT: u16 = 3000;
+
+/// Configuration of a server, as written in the config file.
+#[derive(Clone,Debug,Default,RustcDecodable,RustcEncodable)]
+pub struct Config {
+    /// Port to listen to, for addresses without one.
+    pub port: Option<u16>,
+    /// Addresses to listen to, like `0.0.0.0`, `::` or `[::1]:3000`.
+    ///
+    /// Defaults to localhost.
+    pub bind: Option<Vec<String>>,
+    /// Certificate file, to serve HTTPS.
+    pub cert: Option<String>,
+    /// Private key file, to serve HTTPS.
+    pub key: Option<String>,
+
+    /// Port for the admin interface.
+    pub admin_port: Option<u16>,
+    /// If set, admin requests must send it as a bearer token.
+    pub admin_secret: Option<String>,
+    /// Port for the WebSocket interface, on the same addresses.
+    pub ws_port: Option<u16>,
+    /// Port for the TCP interface, on the same addresses.
+    pub tcp_port: Option<u16>,
+
+    /// Seconds before a join request gives up.
+    pub join_timeout: Option<u32>,
+    /// Seconds before a wait request gives up.
+    pub wait_timeout: Option<u32>,
+    /// Seconds of inactivity before a player is removed.
+    pub idle_timeout: Option<u32>,
+    /// Seconds spectators are kept behind the players.
+    pub spectator_delay: Option<u32>,
+    /// Score ending a party, once a team reaches it.
+    pub target_score: Option<i32>,
+
+    /// Directory where parties are saved.
+    pub data_dir: Option<String>,
+    /// Log filter, like `info` or `coinched=trace`.
+    pub log: Option<String>,
+}
+
+impl Config {
+    /// Reads a config file.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, String> {
+        let path = path.as_ref();
+
+        let mut content = String::new();
+        let read = fs::File::open(path).and_then(|mut file| file.read_to_string(&mut content));
+        if let Err(err) = read {
+            return Err(format!("could not read {}: {}", path.display(), err));
+        }
+
+        json::decode(&content).map_err(|err| format!("invalid config {}: {}", path.display(), err))
+    }
+
+    /// Returns this configuration, where `overrides` sets a value.
+    pub fn merge(self, overrides: Config) -> Config {
+        Config {
+            port: overrides.port.or(self.port),
+            bind: overrides.bind.or(self.bind),
+            cert: overrides.cert.or(self.cert),
+            key: overrides.key.or(self.key),
+            admin_port: overrides.admin_port.or(self.admin_port),
+            admin_secret: overrides.admin_secret.or(self.admin_secret),
+            ws_port: overrides.ws_port.or(self.ws_port),
+            tcp_port: overrides.tcp_port.or(self.tcp_port),
+            join_timeout: overrides.join_timeout.or(self.join_timeout),
+            wait_timeout: overrides.wait_timeout.or(self.wait_timeout),
+            idle_timeout: overrides.idle_timeout.or(self.idle_timeout),
+            spectator_delay: overrides.spectator_delay.or(self.spectator_delay),
+            target_score: overrides.target_score.or(self.target_score),
+            data_dir: overrides.data_dir.or(self.data_dir),
+            log: overrides.log.or(self.log),
+        }
+    }
+
+    /// Returns the settings of the game manager.
+    pub fn settings(&self) -> Settings {
+        let mut settings = Settings::default();
+        if let Some(timeout) = self.join_timeout {
+            settings.join_timeout = timeout.saturating_mul(1000);
+        }
+        if let Some(timeout) = self.wait_timeout {
+            settings.wait_timeout = timeout.saturating_mul(1000);
+        }
+        if let Some(timeout) = self.idle_timeout {
+            settings.idle_timeout = timeout.saturating_mul(1000);
+        }
+        settings.spectator_delay = self.spectator_delay.map(|delay| delay.saturating_mul(1000));
+        settings.target_score = self.target_score;
+        settings.data_dir = self.data_dir.clone();
+        settings
+    }
+
+    /// Resolves the addresses to listen to.
+    pub fn addresses(&self) -> Result<Vec<SocketAddr>, String> {
+        let port = self.port.unwrap_or(DEFAULT_PORT);
+        match self.bind {
+            Some(ref bind) => bind.iter().map(|address| resolve(address, port)).collect(),
+            None => resolve("localhost", port).map(|address| vec![address]),
+        }
+    }
+}
+
+// Resolves an address, using `port` if it doesn't give one.
+fn resolve(address: &str, port: u16) -> Result<SocketAddr, String> {
+    let addresses = match address.to_socket_addrs() {
+        Ok(addresses) => Ok(addresses),
+        Err(_) => (address, port).to_socket_addrs(),
+    };
+
+    match addresses.map(|mut addresses| addresses.next()) {
+        Ok(Some(address)) => Ok(address),
+        Ok(None) => Err(format!("invalid address: `{}` (no match)", address)),
+        Err(err) => Err(format!("invalid address: `{}` ({})", address, err)),
+    }
+}
@@ -0,0 +1,149 @@
+//! Admin console, reading commands from stdin.
+//!
+//! It offers the same tools as the admin HTTP interface, without crafting requests.
+
+use std::io::{self, BufRead, Write};
+use std::process;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::thread;
+
+use {PartyStatus, SeatStatus};
+use super::game_manager::GameManager;
+
+const HELP: &'static str = "Commands:
+* `stats`: number of players waiting, parties, players and spectators
+* `parties`: list every party
+* `show <party>`: show a party and its events
+* `kick <player>`: remove a player, cancelling his party
+* `cancel <party> [message]`: cancel a party
+* `shutdown`: cancel every party, and stop the server";
+
+/// Starts reading commands from stdin, in a new thread.
+pub fn spawn(manager: Arc<GameManager>) -> thread::JoinHandle<()> {
+    thread::spawn(move || run(&manager))
+}
+
+// Reads and runs commands until stdin is closed.
+fn run(manager: &GameManager) {
+    println!("{}", HELP);
+
+    let stdin = io::stdin();
+    prompt();
+    for line in stdin.lock().lines() {
+        let line = match line {
+            Ok(line) => line,
+            Err(err) => {
+                error!("Could not read the console: {}", err);
+                return;
+            }
+        };
+
+        let tokens: Vec<&str> = line.split_whitespace().collect();
+        if !tokens.is_empty() {
+            if let Err(msg) = run_command(manager, &tokens) {
+                println!("{}", msg);
+            }
+        }
+        prompt();
+    }
+}
+
+fn prompt() {
+    print!("> ");
+    io::stdout().flush().unwrap();
+}
+
+// Parses an ID given to a command.
+fn parse_id(what: &str, tokens: &[&str]) -> Result<u32, String> {
+    match tokens.get(1) {
+        Some(value) => {
+            u32::from_str(value)
+                .map_err(|err| format!("invalid {} ID: `{}` ({})", what, value, err))
+        }
+        None => Err(format!("usage: {} <{}>", tokens[0], what)),
+    }
+}
+
+fn run_command(manager: &GameManager, tokens: &[&str]) -> Result<(), String> {
+    match tokens[0] {
+        "help" => println!("{}", HELP),
+        "stats" => {
+            let status = manager.status();
+            println!("Waiting players: {}", status.waiting_list);
+            println!("Private tables: {}", status.tables);
+            println!("Parties: {}", status.parties);
+            println!("Players: {}", status.players);
+            println!("Spectators: {}", status.spectators);
+        }
+        "parties" => {
+            let parties = manager.party_statuses();
+            if parties.is_empty() {
+                println!("No party.");
+            }
+            for party in &parties {
+                print_party(party);
+            }
+        }
+        "show" => {
+            let party_id = try!(parse_id("party", tokens));
+            let party = try!(manager.party_status(party_id).map_err(|err| err.to_string()));
+            let events = try!(manager.party_events(party_id).map_err(|err| err.to_string()));
+            print_party(&party);
+            for seat in &party.seats {
+                print_seat(seat);
+            }
+            for (id, event) in events.iter().enumerate() {
+                println!("{:>4} {} {:?}", id, event.time, event.event);
+            }
+        }
+        "kick" => {
+            let player_id = try!(parse_id("player", tokens));
+            try!(manager.kick(player_id).map_err(|err| err.to_string()));
+            println!("Player {} kicked.", player_id);
+        }
+        "cancel" => {
+            let party_id = try!(parse_id("party", tokens));
+            let msg = if tokens.len() > 2 {
+                Some(tokens[2..].join(" "))
+            } else {
+                None
+            };
+            try!(manager.cancel_party(party_id, msg).map_err(|err| err.to_string()));
+            println!("Party {} cancelled.", party_id);
+        }
+        "shutdown" => {
+            println!("Shutting down.");
+            manager.shutdown("server shutting down");
+            process::exit(0);
+        }
+        _ => return Err(format!("unknown command: `{}` (try `help`)", tokens[0])),
+    }
+
+    Ok(())
+}
+
+fn print_party(party: &PartyStatus) {
+    let names: Vec<&str> = party.seats.iter().map(|seat| &*seat.name).collect();
+    println!("Party {} [{}] {} - scores {:?}, {} deals, {} events",
+             party.party_id,
+             party.phase,
+             names.join(", "),
+             party.scores,
+             party.deals,
+             party.events);
+}
+
+fn print_seat(seat: &SeatStatus) {
+    match (seat.bot, seat.player_id, seat.idle) {
+        (true, _, _) => println!("  {}: {} (bot)", seat.pos as usize, seat.name),
+        (false, Some(player_id), Some(idle)) => {
+            println!("  {}: {} (player {}, idle for {}s)",
+                     seat.pos as usize,
+                     seat.name,
+                     player_id,
+                     idle)
+        }
+        _ => println!("  {}: {} (left)", seat.pos as usize, seat.name),
+    }
+}
@@ -10,6 +10,43 @@ pub enum Error {
     BadPlayerId,
     /// The given event ID is not associated with an actual event
     BadEventId,
+    /// The given deal ID is not associated with a finished deal
+    BadDealId,
+    /// The given party ID is not associated with a party going on
+    BadPartyId,
+    /// The given spectator ID is not associated with a spectator
+    BadSpectatorId,
+
+    /// No party could be found before the join timeout.
+    NoPartyFound,
+    /// No new event happened before the wait timeout.
+    WaitTimeout,
+    /// The party is over: nobody can play anymore.
+    PartyCancelled,
+    /// The given code is not associated with a private table.
+    BadTableCode,
+    /// The requested seat (or team) is not available.
+    SeatTaken,
+    /// The server is stopping, and does not accept new players.
+    ShuttingDown,
+    /// A local party needs between 1 and 4 players.
+    BadPlayerCount,
+    /// Too many event streams are open: poll the events instead.
+    TooManyStreams,
+    /// Too many connections are open on this interface.
+    TooManyConnections,
+
+    /// The token is missing, or does not own the seat.
+    BadToken,
+    /// The name or password doesn't match a registered player.
+    BadCredentials,
+    /// The name is already used by a registered player.
+    NameTaken,
+    /// The name is not valid (empty or too long).
+    BadName,
+
+    /// The action sent on a connection is unknown, or misses a field.
+    BadAction(String),
 
     /// Player tried to play a card during auction.
     PlayInAuction,
@@ -22,11 +59,34 @@ pub enum Error {
     Play(game::PlayError),
 }
 
+impl fmt::Debug for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} ({})", self, self.code())
+    }
+}
+
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
             &Error::BadPlayerId => write!(f, "player not found"),
             &Error::BadEventId => write!(f, "event not found"),
+            &Error::BadDealId => write!(f, "deal not found"),
+            &Error::BadPartyId => write!(f, "party not found"),
+            &Error::BadSpectatorId => write!(f, "spectator not found"),
+            &Error::NoPartyFound => write!(f, "no party found"),
+            &Error::WaitTimeout => write!(f, "no event yet, retry with the same event id"),
+            &Error::PartyCancelled => write!(f, "party cancelled"),
+            &Error::BadTableCode => write!(f, "table not found"),
+            &Error::SeatTaken => write!(f, "seat already taken"),
+            &Error::ShuttingDown => write!(f, "server shutting down"),
+            &Error::BadPlayerCount => write!(f, "a party needs between 1 and 4 players"),
+            &Error::TooManyStreams => write!(f, "too many event streams, use /wait instead"),
+            &Error::TooManyConnections => write!(f, "too many connections, try again later"),
+            &Error::BadToken => write!(f, "invalid token"),
+            &Error::BadCredentials => write!(f, "invalid name or password"),
+            &Error::NameTaken => write!(f, "name already taken"),
+            &Error::BadName => write!(f, "invalid name"),
+            &Error::BadAction(ref msg) => write!(f, "{}", msg),
             &Error::PlayInAuction => write!(f, "cannot play during auction"),
             &Error::BidInGame => write!(f, "cannot bid during card play"),
             &Error::Bid(ref error) => write!(f, "{}", error),
@@ -35,6 +95,39 @@ impl fmt::Display for Error {
     }
 }
 
+impl Error {
+    /// Returns a short identifier for this error.
+    ///
+    /// Unlike the message, it is meant to be matched by clients.
+    pub fn code(&self) -> &'static str {
+        match self {
+            &Error::BadPlayerId => "bad_player_id",
+            &Error::BadEventId => "bad_event_id",
+            &Error::BadDealId => "bad_deal_id",
+            &Error::BadPartyId => "bad_party_id",
+            &Error::BadSpectatorId => "bad_spectator_id",
+            &Error::NoPartyFound => "no_party_found",
+            &Error::WaitTimeout => "wait_timeout",
+            &Error::PartyCancelled => "party_cancelled",
+            &Error::BadTableCode => "bad_table_code",
+            &Error::SeatTaken => "seat_taken",
+            &Error::ShuttingDown => "shutting_down",
+            &Error::BadPlayerCount => "bad_player_count",
+            &Error::TooManyStreams => "too_many_streams",
+            &Error::TooManyConnections => "too_many_connections",
+            &Error::BadToken => "bad_token",
+            &Error::BadCredentials => "bad_credentials",
+            &Error::NameTaken => "name_taken",
+            &Error::BadName => "bad_name",
+            &Error::BadAction(_) => "bad_action",
+            &Error::PlayInAuction => "play_in_auction",
+            &Error::BidInGame => "bid_in_game",
+            &Error::Bid(_) => "bid",
+            &Error::Play(_) => "play",
+        }
+    }
+}
+
 impl From<bid::BidError> for Error {
     fn from(err: bid::BidError) -> Error {
         Error::Bid(err)
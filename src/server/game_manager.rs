@@ -3,44 +3,297 @@
 use rand::{thread_rng, Rng};
 use time;
 
+use std::cmp;
 use std::collections::HashMap;
 use std::sync::{Arc, RwLock, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::Duration;
 
-use eventual::{Future, Complete, Async};
+use eventual::{self, Future, Complete, Async, Timer};
 
 use libcoinche::{bid, cards, pos, game, trick};
 use {Event, EventType, PlayerEvent};
-use {NewPartyInfo, ContractBody, CardBody};
+use {NewPartyInfo, ResumeInfo, TableInfo, LoginInfo, PartySummary, SpectatorInfo};
+use {ServerStatus, PartyStatus, SeatStatus};
+use DealRecord;
+use replay::{self, Replay, TimedEvent};
+use {JoinBody, TableJoinBody, AccountBody, ContractBody, CardBody, ActionBody};
 
 use super::error::Error;
+use super::account::{self, AccountList};
+use super::bot;
+use super::storage::{Storage, PartyHeader, PartyRecord, SeatRecord};
 
 use self::FutureResult::{Ready, Waiting};
 
 enum FutureResult<T: Send + 'static> {
     Ready(T),
-    Waiting(Future<T, ()>),
+    // Also carries the ticket, to cancel the wait.
+    Waiting(usize, Future<T, ()>),
+}
+
+// Outcome of waiting on a future with a deadline.
+enum Timed<T: Send + 'static> {
+    Done(T),
+    // The deadline expired first. Gives the future back.
+    Expired(Future<T, ()>),
+    // The promise was dropped without being completed.
+    Aborted,
+}
+
+// A list of pending promises.
+//
+// Each waiter gets a ticket, so it can remove itself from the list
+// when it gives up. It can also attach some data `D` to its request.
+struct WaitList<T: Send + 'static, D = ()> {
+    next_ticket: usize,
+    waiters: Vec<(usize, D, Complete<T, ()>)>,
+}
+
+impl<T: Send + 'static, D> WaitList<T, D> {
+    fn new() -> Self {
+        WaitList {
+            next_ticket: 0,
+            waiters: Vec::new(),
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.waiters.len()
+    }
+
+    // Adds a new waiter. Returns its ticket, and the future to wait on.
+    fn push(&mut self, data: D) -> (usize, Future<T, ()>) {
+        let ticket = self.next_ticket;
+        self.next_ticket += 1;
+
+        let (promise, future) = Future::pair();
+        self.waiters.push((ticket, data, promise));
+
+        (ticket, future)
+    }
+
+    fn pop(&mut self) -> Option<(D, Complete<T, ()>)> {
+        self.waiters.pop().map(|(_, data, promise)| (data, promise))
+    }
+
+    // Removes all waiters from the list.
+    fn drain(&mut self) -> Vec<(D, Complete<T, ()>)> {
+        self.waiters.drain(..).map(|(_, data, promise)| (data, promise)).collect()
+    }
+
+    // Removes up to `max` waiters whose data match the predicate.
+    fn take_where<F: Fn(&D) -> bool>(&mut self, max: usize, f: F) -> Vec<(D, Complete<T, ()>)> {
+        let mut result = Vec::new();
+        let mut i = 0;
+        while i < self.waiters.len() && result.len() < max {
+            if f(&self.waiters[i].1) {
+                let (_, data, promise) = self.waiters.remove(i);
+                result.push((data, promise));
+            } else {
+                i += 1;
+            }
+        }
+        result
+    }
+
+    // Removes the waiter with the given ticket, and returns its data.
+    // Returns `None` if it was not in the list anymore.
+    fn remove(&mut self, ticket: usize) -> Option<D> {
+        match self.waiters.iter().position(|&(t, _, _)| t == ticket) {
+            Some(i) => Some(self.waiters.remove(i).1),
+            None => None,
+        }
+    }
+}
+
+// Who sits on a seat of a new party.
+enum Seat {
+    // A player waiting for the party to start
+    Waiting(Player, Complete<NewPartyInfo, ()>),
+    // The player starting the party
+    Current(Player),
+    Bot,
+}
+
+// A human about to sit in a party.
+struct Player {
+    name: String,
+    // Token owning the seat, never the login token
+    token: String,
+    // Account the seat is tied to, if he logged in
+    account: Option<String>,
+}
+
+// A private table, waiting for its players.
+struct Table {
+    // Players waiting on each seat, with their ticket
+    seats: Vec<Option<(usize, Player, Complete<NewPartyInfo, ()>)>>,
+    next_ticket: usize,
+    // Last time someone created or tried to join the table,
+    // to forget about unused ones
+    last_used: time::Tm,
+}
+
+impl Table {
+    fn new() -> Self {
+        Table {
+            seats: vec![None, None, None, None],
+            next_ticket: 0,
+            last_used: time::now(),
+        }
+    }
+
+    fn free_seats(&self) -> usize {
+        self.seats.iter().filter(|seat| seat.is_none()).count()
+    }
+
+    // Finds a free seat matching the request.
+    fn find_seat(&self, options: &TableJoinBody) -> ManagerResult<usize> {
+        let free = |i: usize| self.seats[i].is_none();
+        match (options.seat, options.team) {
+            (Some(pos), _) => {
+                if free(pos as usize) {
+                    Ok(pos as usize)
+                } else {
+                    Err(Error::SeatTaken)
+                }
+            }
+            (None, Some(team)) => {
+                (0..4)
+                    .find(|&i| free(i) && pos::PlayerPos::from_n(i).team() == team)
+                    .ok_or(Error::SeatTaken)
+            }
+            (None, None) => (0..4).find(|&i| free(i)).ok_or(Error::SeatTaken),
+        }
+    }
+}
+
+// Creates a random code for a private table, easy to share.
+fn make_table_code() -> String {
+    // Avoid characters too easy to confuse
+    const CHARS: &'static [u8] = b"ABCDEFGHJKLMNPQRSTUVWXYZ23456789";
+    let mut rng = thread_rng();
+    (0..6).map(|_| *rng.choose(CHARS).unwrap() as char).collect()
+}
+
+// Time (in milliseconds) given to the last answers when shutting down.
+const SHUTDOWN_DELAY: u64 = 1000;
+
+// Longest name a player can choose.
+const MAX_NAME_LEN: usize = 32;
+
+// Returns the name to display for a player.
+//
+// Names are trimmed and shortened, and unnamed players get a default one.
+fn make_name(name: &Option<String>) -> String {
+    let name = name.as_ref().map(|name| name.trim()).unwrap_or("");
+    if name.is_empty() {
+        "Anonymous".to_string()
+    } else {
+        name.chars().take(MAX_NAME_LEN).collect()
+    }
+}
+
+// What we know about a player waiting for a party.
+struct JoinRequest {
+    // Does he accept to play with bots?
+    bots: bool,
+    player: Player,
 }
 
 type WaitResult = FutureResult<Event>;
+
 type JoinResult = FutureResult<NewPartyInfo>;
 
+// What a spectator can see right now.
+enum SpectatorWait {
+    Ready(Vec<Event>),
+    // The next event already happened, but is visible in this many milliseconds.
+    Delayed(u32),
+    // Observing the party for the next event.
+    Waiting(Arc<RwLock<Party>>, usize, Future<Event, ()>),
+}
+
 pub type ManagerResult<T> = Result<T, Error>;
 
+/// Tunable parameters for a `GameManager`.
+#[derive(Clone,Debug)]
+pub struct Settings {
+    /// Time (in milliseconds) after which a join request gives up.
+    pub join_timeout: u32,
+    /// Time (in milliseconds) after which a wait request gives up.
+    ///
+    /// It should stay below the timeouts of proxies between the server and the clients.
+    pub wait_timeout: u32,
+    /// Time (in milliseconds) without any request after which a player is removed.
+    pub idle_timeout: u32,
+    /// Time (in milliseconds) spectators lag behind the game.
+    ///
+    /// If set, spectators see every hand. Otherwise, they see no hand at all.
+    pub spectator_delay: Option<u32>,
+    /// Score ending a party, once a team reaches it.
+    ///
+    /// If `None`, parties go on until a player leaves.
+    pub target_score: Option<i32>,
+    /// Directory where parties are saved, to resume them after a restart.
+    ///
+    /// If `None`, parties only live in memory.
+    pub data_dir: Option<String>,
+    /// Seed for the deals, to make parties reproducible.
+    ///
+    /// If `None`, deals are random.
+    pub deal_seed: Option<u32>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            join_timeout: 20_000,
+            wait_timeout: 15_000,
+            idle_timeout: 600_000,
+            spectator_delay: None,
+            target_score: None,
+            data_dir: None,
+            deal_seed: None,
+        }
+    }
+}
 
 /// Base class for managing matchmaking.
 ///
+/// Players can either join the anonymous queue, or sit at a private table.
+///
 /// It is the main entry point for the server API.
 /// It offers a thread-safe access to various actions.
 pub struct GameManager {
+    settings: Settings,
+
     party_list: RwLock<PlayerList>,
 
-    waiting_list: Mutex<Vec<Complete<NewPartyInfo, ()>>>,
+    waiting_list: Mutex<WaitList<NewPartyInfo, JoinRequest>>,
+
+    // Private tables, by code
+    tables: Mutex<HashMap<String, Table>>,
+
+    accounts: RwLock<AccountList>,
+
+    storage: Option<Arc<Storage>>,
+
+    // Set once the server is shutting down
+    closing: AtomicBool,
+
+    // Used to schedule wait timeouts
+    timer: Mutex<Timer>,
 }
 
 /// Describe a single game.
 pub enum Game {
-    /// The game is still in the auction phase
-    Bidding(bid::Auction),
+    /// The game is still in the auction phase, with the first player and the hands.
+    ///
+    /// The auction deals its own hands: only its bids are used.
+    Bidding(pos::PlayerPos, [cards::Hand; 4], bid::Auction),
     /// The game is in the main playing phase
     Playing(game::GameState),
 }
@@ -48,7 +301,7 @@ pub enum Game {
 impl Game {
     fn next_player(&self) -> pos::PlayerPos {
         match self {
-            &Game::Bidding(ref auction) => auction.next_player(),
+            &Game::Bidding(_, _, ref auction) => auction.next_player(),
             &Game::Playing(ref game) => game.next_player(),
         }
     }
@@ -56,90 +309,398 @@ impl Game {
 
 // Creates a new game, starting with an auction.
 // Also returns a NewGame Event with the players cards.
-fn make_game(first: pos::PlayerPos) -> (bid::Auction, EventType) {
-    let auction = bid::Auction::new(first);
-    let hands = auction.hands();
+//
+// With a seed, the same deal number always gets the same hands.
+fn make_game(first: pos::PlayerPos, seed: Option<u32>, deal: u32) -> (Game, EventType) {
+    let hands = match seed {
+        Some(seed) => cards::deal_seeded_hands(&[seed, deal]),
+        None => cards::deal_hands(),
+    };
 
     let event = EventType::NewGame {
         first: first,
         hands: hands,
     };
 
-    (auction, event)
+    (Game::Bidding(first, hands, bid::Auction::new(first)), event)
 }
 
 /// Represents a party
 struct Party {
+    id: u32,
+
     game: Game,
+    // First player of the next deal
     first: pos::PlayerPos,
 
+    // Seats taken by bots
+    bots: [bool; 4],
+    // Name of each seat
+    names: Vec<String>,
+
     scores: [i32; 2],
 
     events: Vec<EventType>,
-    observers: Mutex<Vec<Complete<Event, ()>>>,
+    // When each event happened
+    times: Vec<time::Tm>,
+    observers: Mutex<WaitList<Event>>,
+
+    // ID of the event that started the current deal
+    deal_start: usize,
+    // Deals already finished
+    history: Vec<DealRecord>,
+
+    // Score ending the party, if any
+    target_score: Option<i32>,
+    // Seed for the deals, if they must be reproducible
+    deal_seed: Option<u32>,
+    // Set once a PartyCancelled event was sent.
+    cancelled: bool,
+
+    // Where events are saved, if anywhere
+    storage: Option<Arc<Storage>>,
 }
 
 impl Party {
-    fn new(first: pos::PlayerPos) -> Self {
-        let (auction, event) = make_game(first);
-        Party {
-            first: first,
-            game: Game::Bidding(auction),
+    fn new(id: u32,
+           first: pos::PlayerPos,
+           bots: [bool; 4],
+           names: Vec<String>,
+           target_score: Option<i32>,
+           deal_seed: Option<u32>,
+           storage: Option<Arc<Storage>>)
+           -> Self {
+        let (game, event) = make_game(first, deal_seed, 0);
+        let mut party = Party {
+            id: id,
+            // This deal is already made: the next one goes to the next player.
+            first: first.next(),
+            bots: bots,
+            names: names,
+            game: game,
             scores: [0; 2],
-            events: vec![event],
-            observers: Mutex::new(Vec::new()),
+            events: Vec::new(),
+            times: Vec::new(),
+            observers: Mutex::new(WaitList::new()),
+            deal_start: 0,
+            history: Vec::new(),
+            target_score: target_score,
+            deal_seed: deal_seed,
+            cancelled: false,
+            storage: storage,
+        };
+        party.add_event(event);
+        party
+    }
+
+    // Rebuilds a party from its saved events.
+    //
+    // A deal still in auction is not rebuilt: it is cancelled and dealt again.
+    fn restore(record: PartyRecord,
+               target_score: Option<i32>,
+               deal_seed: Option<u32>,
+               storage: Arc<Storage>)
+               -> Result<Self, String> {
+        let times: Vec<time::Tm> = record.events
+                                         .iter()
+                                         .map(|event| event.tm().unwrap_or_else(time::now))
+                                         .collect();
+        let events: Vec<EventType> = record.events.into_iter().map(|event| event.event).collect();
+
+        let mut scores = [0; 2];
+        let mut deal = None;
+        let mut history = Vec::new();
+        for (i, event) in events.iter().enumerate() {
+            match event {
+                &EventType::NewGame { first, hands } => {
+                    if let Some((start, _, _)) = deal {
+                        history.extend(replay::make_deal_record(&events[start..i]));
+                    }
+                    deal = Some((i, first, hands));
+                }
+                &EventType::GameOver { scores: deal_scores, .. } => {
+                    for j in 0..2 {
+                        scores[j] += deal_scores[j];
+                    }
+                }
+                _ => (),
+            }
         }
+        let (deal_start, first, hands) = try!(deal.ok_or("no deal found".to_string()));
+
+        let mut contract = None;
+        let mut cards = Vec::new();
+        // Was the outcome of the last card saved?
+        let mut trick_over = false;
+        let mut over = false;
+        for event in &events[deal_start..] {
+            match event {
+                &EventType::BidOver(ref c) => contract = Some(c.clone()),
+                &EventType::FromPlayer(pos, PlayerEvent::CardPlayed(card)) => {
+                    cards.push((pos, card));
+                    trick_over = false;
+                }
+                &EventType::TrickOver { .. } => trick_over = true,
+                &EventType::GameOver { .. } => over = true,
+                _ => (),
+            }
+        }
+
+        let in_auction = contract.is_none();
+        let mut last_result = game::TrickResult::Nothing;
+        let game = match contract {
+            Some(contract) => {
+                let mut game = game::GameState::new(first, hands, contract);
+                for (pos, card) in cards {
+                    last_result = try!(game.play_card(pos, card).map_err(|err| err.to_string()));
+                }
+                Game::Playing(game)
+            }
+            None => Game::Bidding(first, hands, bid::Auction::new(first)),
+        };
+
+        let mut party = Party {
+            id: record.id,
+            // Like in `Party::new`, the next deal goes to the next player.
+            first: first.next(),
+            bots: record.header.bots,
+            names: record.header.names,
+            game: game,
+            scores: scores,
+            events: events,
+            times: times,
+            observers: Mutex::new(WaitList::new()),
+            deal_start: deal_start,
+            history: history,
+            target_score: target_score,
+            deal_seed: deal_seed,
+            cancelled: false,
+            storage: Some(storage),
+        };
+
+        // We may have stopped before saving what the last card caused.
+        if let game::TrickResult::TrickOver(winner, game_result) = last_result {
+            if !trick_over {
+                party.add_event(EventType::TrickOver { winner: winner });
+            }
+            if let game::GameResult::GameOver { points, winners, scores } = game_result {
+                if !over {
+                    for i in 0..2 {
+                        party.scores[i] += scores[i];
+                    }
+                    party.add_event(EventType::GameOver {
+                        points: points,
+                        winner: winners,
+                        scores: scores,
+                    });
+                    over = true;
+                }
+            }
+        }
+
+        if over {
+            // We stopped right between two deals.
+            party.end_deal();
+        } else if in_auction {
+            party.redeal(first);
+        }
+
+        Ok(party)
     }
 
     fn add_event(&mut self, event: EventType) -> Event {
         trace!("Adding event: {:?}", event);
+        let now = time::now();
+        if let Some(ref storage) = self.storage {
+            if let Err(err) = storage.append(self.id, &TimedEvent::new(event.clone(), &now)) {
+                error!("Could not save an event of party {}: {}", self.id, err);
+            }
+        }
         let ev = Event {
             event: event.clone(),
             id: self.events.len(),
         };
         let mut observers = self.observers.lock().unwrap();
-        for promise in observers.drain(..) {
-            // TODO: handle cancelled wait?
+        // Observers who gave up already removed themselves.
+        for (_, promise) in observers.drain() {
             promise.complete(ev.clone());
         }
         self.events.push(event);
+        self.times.push(now);
 
         ev
     }
 
+    // Returns the party so far, as a replay.
+    //
+    // Hands of the current deal stay hidden, unless the party is over.
+    fn replay(&self) -> Replay {
+        let end = if self.cancelled {
+            self.events.len()
+        } else {
+            self.deal_start
+        };
+
+        Replay {
+            version: replay::FORMAT_VERSION,
+            names: self.names.clone(),
+            bots: self.bots,
+            events: self.events[..end]
+                        .iter()
+                        .zip(&self.times)
+                        .map(|(event, time)| TimedEvent::new(event.clone(), time))
+                        .collect(),
+        }
+    }
+
+    // Name of the current phase, for the admin.
+    fn phase(&self) -> &'static str {
+        match self.game {
+            _ if self.cancelled => "Cancelled",
+            Game::Bidding(..) => "Bidding",
+            Game::Playing(_) => "Playing",
+        }
+    }
+
+    // Actions are refused once the party is cancelled: nothing may follow
+    // its PartyCancelled event.
+    fn check_going(&self) -> ManagerResult<()> {
+        if self.cancelled {
+            Err(Error::PartyCancelled)
+        } else {
+            Ok(())
+        }
+    }
+
     fn get_auction_mut(&mut self) -> ManagerResult<&mut bid::Auction> {
         match self.game {
-            Game::Bidding(ref mut auction) => Ok(auction),
+            Game::Bidding(_, _, ref mut auction) => Ok(auction),
             Game::Playing(_) => Err(Error::BidInGame),
         }
     }
 
     fn get_game(&self) -> ManagerResult<&game::GameState> {
         match self.game {
-            Game::Bidding(_) => Err(Error::PlayInAuction),
+            Game::Bidding(..) => Err(Error::PlayInAuction),
             Game::Playing(ref game) => Ok(game),
         }
     }
 
     fn get_game_mut(&mut self) -> ManagerResult<&mut game::GameState> {
         match self.game {
-            Game::Bidding(_) => Err(Error::PlayInAuction),
+            Game::Bidding(..) => Err(Error::PlayInAuction),
             Game::Playing(ref mut game) => Ok(game),
         }
     }
 
+    // Keeps the current deal in the history.
+    fn archive_deal(&mut self) {
+        let record = replay::make_deal_record(&self.events[self.deal_start..]);
+        self.history.extend(record);
+    }
+
     fn next_game(&mut self) {
-        // TODO: Maybe keep the current game in the history?
+        self.archive_deal();
 
-        let (auction, event) = make_game(self.first);
+        let (game, event) = make_game(self.first, self.deal_seed, self.events.len() as u32);
 
         self.first = self.first.next();
-        self.game = Game::Bidding(auction);
+        self.game = game;
+        self.deal_start = self.events.len();
         self.add_event(event);
     }
 
+    // Starts the next deal, unless a team reached the target score.
+    fn end_deal(&mut self) {
+        match self.target_score {
+            Some(target) if self.scores.iter().any(|&score| score >= target) => {
+                self.archive_deal();
+                let msg = format!("party over, final scores: {:?}", self.scores);
+                self.cancel(msg);
+            }
+            _ => self.next_game(),
+        }
+    }
+
+    // Cancels the current auction, and deals again with the same first player.
+    fn redeal(&mut self, first: pos::PlayerPos) {
+        let (game, event) = make_game(first, self.deal_seed, self.events.len() as u32);
+
+        // We may have stopped right after an auction was cancelled.
+        match self.events.last() {
+            Some(&EventType::BidCancelled) => (),
+            _ => {
+                self.add_event(EventType::BidCancelled);
+            }
+        }
+        self.archive_deal();
+        self.game = game;
+        self.deal_start = self.events.len();
+        self.add_event(event);
+    }
+
+    // Returns `true` if someone already bid in the current auction.
+    fn auction_opened(&self) -> bool {
+        self.events[self.deal_start..].iter().any(|event| {
+            match event {
+                &EventType::FromPlayer(_, PlayerEvent::Bidded(_, _)) => true,
+                _ => false,
+            }
+        })
+    }
+
+    // Lets the bots act until a human has to play.
+    //
+    // Bots go through the same actions as humans, as soon as it's their turn.
+    fn play_bots(&mut self) {
+        while !self.cancelled {
+            let pos = self.game.next_player();
+            if !self.bots[pos as usize] {
+                break;
+            }
+
+            let result = match bot::decide(&self.game, pos, self.auction_opened()) {
+                bot::Action::Pass => self.pass(pos),
+                bot::Action::Bid(suit, target) => {
+                    match self.bid(pos, suit, target) {
+                        Ok(event) => Ok(event),
+                        // Maybe someone overbid us. Let it go.
+                        Err(_) => self.pass(pos),
+                    }
+                }
+                bot::Action::PlayCard(card) => self.play_card(pos, card),
+            };
+
+            if let Err(err) = result {
+                // The bot would be stuck forever: better give up now.
+                error!("Bot {:?} failed to play: {}", pos, err);
+                let msg = format!("bot error: {} ({})", self.names[pos as usize], pos as usize);
+                self.cancel(msg);
+            }
+        }
+    }
+
+    // Cancels the party because the server stops.
+    //
+    // Players are told right away, but the party stays on disk to be resumed after a restart.
+    fn shut_down(&mut self, msg: String) {
+        self.storage = None;
+        self.cancel(msg);
+    }
+
     fn cancel(&mut self, msg: String) {
+        if self.cancelled {
+            return;
+        }
+        self.cancelled = true;
         self.add_event(EventType::PartyCancelled(msg));
+
+        // Nothing left to resume.
+        if let Some(ref storage) = self.storage {
+            if let Err(err) = storage.remove(self.id) {
+                error!("Could not remove party {}: {}", self.id, err);
+            }
+        }
     }
 
     fn bid(&mut self,
@@ -148,6 +709,7 @@ impl Party {
            target: bid::Target)
            -> ManagerResult<Event> {
         trace!("Bid from {:?}: {:?} on {:?}", pos, target, trump);
+        try!(self.check_going());
         let state = {
             let auction = try!(self.get_auction_mut());
             try!(auction.bid(pos, trump, target))
@@ -165,6 +727,7 @@ impl Party {
     }
 
     fn pass(&mut self, pos: pos::PlayerPos) -> Result<Event, Error> {
+        try!(self.check_going());
         let state = {
             let auction = try!(self.get_auction_mut());
             try!(auction.pass(pos))
@@ -184,6 +747,7 @@ impl Party {
     }
 
     fn coinche(&mut self, pos: pos::PlayerPos) -> Result<Event, Error> {
+        try!(self.check_going());
         let state = {
             let auction = try!(self.get_auction_mut());
             try!(auction.coinche(pos))
@@ -201,9 +765,10 @@ impl Party {
     fn complete_auction(&mut self) {
         let game = match &mut self.game {
             &mut Game::Playing(_) => unreachable!(),
-            &mut Game::Bidding(ref mut auction) => {
+            &mut Game::Bidding(first, hands, ref mut auction) => {
+                // The auction deals its own hands: only keep the contract.
                 match auction.complete() {
-                    Ok(game) => game,
+                    Ok(game) => game::GameState::new(first, hands, game.contract().clone()),
                     Err(err) => panic!(err),
                 }
             }
@@ -215,6 +780,7 @@ impl Party {
     }
 
     fn play_card(&mut self, pos: pos::PlayerPos, card: cards::Card) -> Result<Event, Error> {
+        try!(self.check_going());
         let result = {
             let game = try!(self.get_game_mut());
             try!(game.play_card(pos, card))
@@ -238,7 +804,7 @@ impl Party {
                             winner: winners,
                             scores: scores,
                         });
-                        self.next_game();
+                        self.end_deal();
                     }
                 }
             }
@@ -257,21 +823,90 @@ struct PlayerInfo {
     // Last time we received something from him
     // (to detect inactivity, and disconnect him)
     pub last_time: Mutex<time::Tm>,
+    // Secret token owning the seat
+    pub token: String,
+    // Account the seat is tied to, if any
+    pub account: Option<String>,
+}
+
+// Information for someone watching a party
+struct SpectatorEntry {
+    pub party: Arc<RwLock<Party>>,
+    // To forget about spectators who left
+    pub last_time: Mutex<time::Tm>,
 }
 
 // Maps player IDs to PlayerInfo
 struct PlayerList {
     pub player_map: HashMap<u32, PlayerInfo>,
+    // Players who left their party, kept a while so they can still get its replay
+    pub released: HashMap<u32, PlayerInfo>,
+    pub spectators: HashMap<u32, SpectatorEntry>,
+    // Parties going on, by ID
+    pub parties: HashMap<u32, Arc<RwLock<Party>>>,
+    // ID for the next party
+    pub next_party_id: u32,
 }
 
 impl PlayerList {
     fn new() -> PlayerList {
-        PlayerList { player_map: HashMap::new() }
+        PlayerList {
+            player_map: HashMap::new(),
+            released: HashMap::new(),
+            spectators: HashMap::new(),
+            parties: HashMap::new(),
+            next_party_id: 0,
+        }
+    }
+
+    // Is this ID already used by a player or a spectator?
+    fn id_taken(&self, id: u32) -> bool {
+        self.player_map.contains_key(&id) || self.released.contains_key(&id) ||
+        self.spectators.contains_key(&id)
+    }
+
+    // Creates a random ID for a new spectator.
+    fn make_spectator_id(&self) -> u32 {
+        loop {
+            let id = thread_rng().next_u32();
+            if !self.id_taken(id) {
+                return id;
+            }
+        }
+    }
+
+    fn get_spectator(&self, spectator_id: u32) -> Result<&SpectatorEntry, Error> {
+        match self.spectators.get(&spectator_id) {
+            None => Err(Error::BadSpectatorId),
+            Some(spectator) => {
+                *spectator.last_time.lock().unwrap() = time::now();
+                Ok(spectator)
+            }
+        }
+    }
+
+    // Like `get_player_info`, but also finds released players.
+    fn get_any_player_info(&self, player_id: u32, token: &str) -> Result<&PlayerInfo, Error> {
+        match self.released.get(&player_id) {
+            Some(info) if info.token != token => Err(Error::BadToken),
+            Some(info) => Ok(info),
+            None => self.get_player_info(player_id, token),
+        }
+    }
+
+    // Takes a player out of his party.
+    fn release(&mut self, player_id: u32) {
+        if let Some(info) = self.player_map.remove(&player_id) {
+            *info.last_time.lock().unwrap() = time::now();
+            self.released.insert(player_id, info);
+        }
     }
 
-    fn get_player_info(&self, player_id: u32) -> Result<&PlayerInfo, Error> {
+    // Returns the player info, if the token owns the seat.
+    fn get_player_info(&self, player_id: u32, token: &str) -> Result<&PlayerInfo, Error> {
         match self.player_map.get(&player_id) {
             None => Err(Error::BadPlayerId),
+            Some(info) if info.token != token => Err(Error::BadToken),
             Some(info) => {
                 // Update the last active time
                 *info.last_time.lock().unwrap() = time::now();
@@ -291,7 +926,7 @@ impl PlayerList {
             loop {
                 let id = thread_rng().next_u32();
                 // println!("New UUID: {}", id);
-                if self.player_map.contains_key(&id) {
+                if self.id_taken(id) {
                     continue;
                 }
                 let mut ok = true;
@@ -311,13 +946,17 @@ impl PlayerList {
         result
     }
 
-    fn remove(&mut self, player_id: u32) -> Result<(), Error> {
+    // Removes a player, and cancels his party (if it wasn't already).
+    // `reason` is used in the cancel message.
+    fn remove(&mut self, player_id: u32, reason: &str) -> Result<(), Error> {
         {
-            let info = try!(self.get_player_info(player_id));
+            let info = try!(self.player_map.get(&player_id).ok_or(Error::BadPlayerId));
             let pos = info.pos;
-            info.party.write().unwrap().cancel(format!("player left: {}", pos as usize));
+            let mut party = info.party.write().unwrap();
+            let msg = format!("{}: {} ({})", reason, party.names[pos as usize], pos as usize);
+            party.cancel(msg);
         }
-        self.player_map.remove(&player_id);
+        self.release(player_id);
 
         Ok(())
     }
@@ -325,43 +964,283 @@ impl PlayerList {
 
 
 impl GameManager {
-    pub fn new() -> GameManager {
-        GameManager {
+    /// Creates a new manager.
+    ///
+    /// If `settings.data_dir` is set, unfinished parties saved there are
+    /// loaded back, so their players can resume them.
+    ///
+    /// Fails if the data directory can't be opened.
+    pub fn new(settings: Settings) -> Result<GameManager, String> {
+        let storage = match settings.data_dir {
+            Some(ref dir) => {
+                match Storage::open(dir) {
+                    Ok(storage) => Some(Arc::new(storage)),
+                    Err(err) => {
+                        return Err(format!("could not open the data directory {}: {}", dir, err))
+                    }
+                }
+            }
+            None => None,
+        };
+
+        let manager = GameManager {
+            settings: settings,
             party_list: RwLock::new(PlayerList::new()),
-            waiting_list: Mutex::new(Vec::new()),
+            waiting_list: Mutex::new(WaitList::new()),
+            tables: Mutex::new(HashMap::new()),
+            accounts: RwLock::new(AccountList::new()),
+            storage: storage,
+            closing: AtomicBool::new(false),
+            timer: Mutex::new(Timer::new()),
+        };
+
+        manager.load_parties();
+
+        Ok(manager)
+    }
+
+    // Loads back the parties saved in the storage, if any.
+    fn load_parties(&self) {
+        let storage = match self.storage {
+            Some(ref storage) => storage.clone(),
+            None => return,
+        };
+
+        let records = match storage.load() {
+            Ok(records) => records,
+            Err(err) => {
+                error!("Could not load the saved parties: {}", err);
+                return;
+            }
+        };
+
+        let mut list = self.party_list.write().unwrap();
+        for record in records {
+            let id = record.id;
+            if list.next_party_id <= id {
+                list.next_party_id = id + 1;
+            }
+
+            let cancelled = record.events.iter().any(|event| {
+                match event.event {
+                    EventType::PartyCancelled(_) => true,
+                    _ => false,
+                }
+            });
+            if cancelled {
+                // We stopped before forgetting about it.
+                storage.remove(id).ok();
+                continue;
+            }
+
+            let seats = record.header.seats.clone();
+            let party = match Party::restore(record,
+                                             self.settings.target_score,
+                                             self.settings.deal_seed,
+                                             storage.clone()) {
+                Ok(party) => Arc::new(RwLock::new(party)),
+                Err(err) => {
+                    warn!("Could not restore party {}: {}", id, err);
+                    continue;
+                }
+            };
+            party.write().unwrap().play_bots();
+            list.parties.insert(id, party.clone());
+
+            trace!("Party restored: {}", id);
+            for seat in seats {
+                list.player_map.insert(seat.player_id,
+                                       PlayerInfo {
+                                           party: party.clone(),
+                                           pos: seat.pos,
+                                           last_time: Mutex::new(time::now()),
+                                           token: seat.token,
+                                           account: seat.account,
+                                       });
+            }
+        }
+    }
+
+    // Waits until the future is ready, or until `timeout` milliseconds passed.
+    fn await_timeout<T: Send + 'static>(&self, future: Future<T, ()>, timeout: u32) -> Timed<T> {
+        let deadline = self.timer.lock().unwrap().timeout_ms(timeout);
+        match eventual::select((future, deadline)).await() {
+            Ok((0, (future, _))) => {
+                match future.await() {
+                    Ok(value) => Timed::Done(value),
+                    Err(_) => Timed::Aborted,
+                }
+            }
+            Ok((_, (future, _))) => Timed::Expired(future),
+            Err(_) => Timed::Aborted,
         }
     }
 
-    /// Attempts to join a new party. Blocks until a party is available.
-    pub fn join(&self) -> ManagerResult<NewPartyInfo> {
-        trace!("Join");
-        match self.get_join_result() {
-            // TODO: add a timeout (max: 20s)
-            // TODO: handle cancelled join?
+    /// Attempts to join a new party.
+    ///
+    /// Blocks until a party is available, or until the join timeout expires,
+    /// in which case it returns `Error::NoPartyFound`.
+    ///
+    /// If `options.bots_after` is set, empty seats are given to bots after
+    /// this many seconds instead.
+    ///
+    /// `login` is the token of a logged in player, if any.
+    pub fn join(&self, options: JoinBody, login: Option<&str>) -> ManagerResult<NewPartyInfo> {
+        trace!("Join: {:?}", options);
+        try!(self.check_open());
+
+        let player = try!(self.make_player(&options.name, login));
+
+        // Waiting for bots replaces the join timeout, even when it's longer:
+        // a retry would start waiting all over again.
+        let timeout = match options.bots_after {
+            Some(seconds) => seconds.saturating_mul(1000),
+            None => self.settings.join_timeout,
+        };
+        let bots_after_timeout = options.bots_after.is_some();
+
+        match self.get_join_result(&options, player) {
             Ready(info) => Ok(info),
-            Waiting(future) => Ok(future.await().unwrap()),
+            Waiting(ticket, future) => {
+                match self.await_timeout(future, timeout) {
+                    Timed::Done(info) => Ok(info),
+                    Timed::Expired(future) => {
+                        // Leave the queue, so no party is made with us.
+                        let mut waiters = self.waiting_list.lock().unwrap();
+                        if let Some(request) = waiters.remove(ticket) {
+                            if bots_after_timeout {
+                                trace!("Join timed out, filling with bots");
+                                let others = waiters.take_where(2, |request| request.bots);
+                                Ok(self.make_party(request.player, others))
+                            } else {
+                                trace!("Join timed out");
+                                Err(Error::NoPartyFound)
+                            }
+                        } else {
+                            // Too late: a party was made while we timed out.
+                            // The promise is completed under the waiting list lock,
+                            // so this won't block.
+                            future.await().map_err(|_| Error::NoPartyFound)
+                        }
+                    }
+                    Timed::Aborted => {
+                        // The queue is dropped when the server stops.
+                        try!(self.check_open());
+                        Err(Error::NoPartyFound)
+                    }
+                }
+            }
         }
     }
 
-    fn get_join_result(&self) -> JoinResult {
+    // Returns the player who is about to sit, checking his login token if any.
+    fn make_player(&self, name: &Option<String>, login: Option<&str>) -> ManagerResult<Player> {
+        match login {
+            Some(token) => {
+                match self.accounts.read().unwrap().user(token) {
+                    // The seat gets its own token: logging out doesn't leave it.
+                    Some(user) => {
+                        Ok(Player {
+                            name: user.to_string(),
+                            token: account::make_token(),
+                            account: Some(user.to_string()),
+                        })
+                    }
+                    None => Err(Error::BadToken),
+                }
+            }
+            None => {
+                Ok(Player {
+                    name: make_name(name),
+                    token: account::make_token(),
+                    account: None,
+                })
+            }
+        }
+    }
+
+    fn get_join_result(&self, options: &JoinBody, player: Player) -> JoinResult {
         let mut waiters = self.waiting_list.lock().unwrap();
         // println!("Waiters: {}", waiters.len());
         if waiters.len() >= 3 {
             // It's a PARTEY!
-            let info = self.make_party(vec![
+            let info = self.make_party(player, vec![
                waiters.pop().unwrap(),
                waiters.pop().unwrap(),
                waiters.pop().unwrap(),
             ]);
             return Ready(info);
+        } else if options.bots_after == Some(0) {
+            // Don't wait: take whoever accepts bots, and complete with bots.
+            let others = waiters.take_where(3, |request| request.bots);
+            return Ready(self.make_party(player, others));
         } else {
-            let (promise, future) = Future::pair();
-            waiters.push(promise);
-            return Waiting(future);
+            let (ticket, future) = waiters.push(JoinRequest {
+                bots: options.bots_after.is_some(),
+                player: player,
+            });
+            return Waiting(ticket, future);
+        }
+    }
+
+    // Makes a party with the given waiting players, and the current one.
+    // Empty seats, if any, are given to bots.
+    fn make_party(&self,
+                  player: Player,
+                  others: Vec<(JoinRequest, Complete<NewPartyInfo, ()>)>)
+                  -> NewPartyInfo {
+        // Humans sit first, then bots.
+        let mut seats: Vec<Seat> = others.into_iter()
+                                         .map(|(request, promise)| {
+                                             Seat::Waiting(request.player, promise)
+                                         })
+                                         .collect();
+        seats.push(Seat::Current(player));
+        while seats.len() < 4 {
+            seats.push(Seat::Bot);
         }
+
+        self.start_party(seats)
+    }
+
+    /// Starts a party right away, with the given players first and bots on the other seats.
+    ///
+    /// Meant for offline play, where every human shares the same process.
+    /// Returns the info of each player, in order, or `Error::BadPlayerCount`
+    /// unless there are between 1 and 4 of them.
+    pub fn start_local_party(&self, names: &[Option<String>]) -> ManagerResult<Vec<NewPartyInfo>> {
+        try!(self.check_open());
+        if names.is_empty() || names.len() > 4 {
+            return Err(Error::BadPlayerCount);
+        }
+
+        let mut futures = Vec::new();
+        let mut seats = Vec::new();
+        for (i, name) in names.iter().enumerate() {
+            let player = try!(self.make_player(name, None));
+            if i == 0 {
+                seats.push(Seat::Current(player));
+            } else {
+                let (promise, future) = Future::pair();
+                seats.push(Seat::Waiting(player, promise));
+                futures.push(future);
+            }
+        }
+        while seats.len() < 4 {
+            seats.push(Seat::Bot);
+        }
+
+        let mut infos = vec![self.start_party(seats)];
+        // The other players were told right away.
+        for future in futures {
+            infos.push(try!(future.await().map_err(|_| Error::NoPartyFound)));
+        }
+        Ok(infos)
     }
 
-    fn make_party(&self, others: Vec<Complete<NewPartyInfo, ()>>) -> NewPartyInfo {
+    // Starts a party with the given 4 seats, and tells everyone waiting.
+    // Returns the info for the current player.
+    fn start_party(&self, seats: Vec<Seat>) -> NewPartyInfo {
         let mut list = self.party_list.write().unwrap();
 
         // Generate 4 new IDS
@@ -369,100 +1248,370 @@ impl GameManager {
 
         // println!("IDS: {:?}", ids);
 
-        let party = Arc::new(RwLock::new(Party::new(pos::PlayerPos::P0)));
+        let mut bots = [false; 4];
+        let mut names = Vec::new();
+        for (i, seat) in seats.iter().enumerate() {
+            names.push(match seat {
+                &Seat::Waiting(ref player, _) => player.name.clone(),
+                &Seat::Current(ref player) => player.name.clone(),
+                &Seat::Bot => {
+                    bots[i] = true;
+                    format!("Bot {}", i)
+                }
+            });
+        }
+
+        let party_id = list.next_party_id;
+        list.next_party_id += 1;
+
+        // Save the seats first: the events will follow.
+        let storage = self.storage.as_ref().and_then(|storage| {
+            let header = PartyHeader {
+                bots: bots,
+                names: names.clone(),
+                seats: seats.iter()
+                            .enumerate()
+                            .filter_map(|(i, seat)| {
+                                let player = match seat {
+                                    &Seat::Waiting(ref player, _) => player,
+                                    &Seat::Current(ref player) => player,
+                                    &Seat::Bot => return None,
+                                };
+                                Some(SeatRecord {
+                                    player_id: ids[i],
+                                    pos: pos::PlayerPos::from_n(i),
+                                    token: player.token.clone(),
+                                    account: player.account.clone(),
+                                })
+                            })
+                            .collect(),
+            };
+            match storage.create(party_id, &header) {
+                Ok(()) => Some(storage.clone()),
+                Err(err) => {
+                    error!("Could not save party {}: {}", party_id, err);
+                    None
+                }
+            }
+        });
+
+        let party = Arc::new(RwLock::new(Party::new(party_id,
+                                                    pos::PlayerPos::P0,
+                                                    bots,
+                                                    names.clone(),
+                                                    self.settings.target_score,
+                                                    self.settings.deal_seed,
+                                                    storage)));
         // Kickstart it with a new game!
+        party.write().unwrap().play_bots();
+        list.parties.insert(party_id, party.clone());
+
+        trace!("Party ready: {:?}", ids);
+
+        // Tell everyone. They'll love it.
+        // Waiters who gave up already removed themselves,
+        // so everyone here is still listening.
+        let mut current = None;
+        for (i, seat) in seats.into_iter().enumerate() {
+            let (player, promise) = match seat {
+                Seat::Bot => continue,
+                Seat::Waiting(player, promise) => (player, Some(promise)),
+                Seat::Current(player) => (player, None),
+            };
+
+            let info = NewPartyInfo {
+                player_id: ids[i],
+                player_pos: pos::PlayerPos::from_n(i),
+                names: names.clone(),
+                token: player.token.clone(),
+            };
+            trace!("Player {} sits at {:?} (account: {:?})",
+                   ids[i],
+                   info.player_pos,
+                   player.account);
 
-        // Prepare the players info
-        for i in 0..4 {
             list.player_map.insert(ids[i],
                                    PlayerInfo {
                                        party: party.clone(),
-                                       pos: pos::PlayerPos::from_n(i),
+                                       pos: info.player_pos,
                                        last_time: Mutex::new(time::now()),
+                                       token: player.token,
+                                       account: player.account,
                                    });
+
+            match promise {
+                Some(promise) => promise.complete(info),
+                // Even you, weird last dude.
+                None => current = Some(info),
+            }
         }
 
-        trace!("Party ready: {:?}", ids);
+        current.expect("no current player in the party")
+    }
 
-        // Tell everyone. They'll love it.
-        // TODO: handle cancelled channels (?)
-        // println!("Waking them up!");
-        for (i, promise) in others.into_iter().enumerate() {
-            promise.complete(NewPartyInfo {
-                player_id: ids[i],
-                player_pos: pos::PlayerPos::from_n(i),
-            });
+    /// Registers a new player, who can then log in.
+    pub fn register(&self, account: AccountBody) -> ManagerResult<()> {
+        let name = account.name.trim();
+        if name.is_empty() || name.chars().count() > MAX_NAME_LEN {
+            return Err(Error::BadName);
+        }
+
+        trace!("Registering {}", name);
+        // Hashing takes a while: don't block the other players meanwhile.
+        let hash = account::hash_password(&account.password);
+        self.accounts.write().unwrap().register(name, hash)
+    }
+
+    /// Checks a player's password, and returns a login token.
+    ///
+    /// The token can then be used to join parties under the player's name.
+    pub fn login(&self, account: AccountBody) -> ManagerResult<LoginInfo> {
+        let name = account.name.trim();
+        // Check the password without holding the lock: it takes a while.
+        let hash = self.accounts.read().unwrap().hash(name);
+        let valid = match hash {
+            Some(hash) => account::check_password(&account.password, &hash),
+            None => false,
+        };
+        if !valid {
+            return Err(Error::BadCredentials);
+        }
+
+        let token = self.accounts.write().unwrap().login(name);
+        Ok(LoginInfo { token: token })
+    }
+
+    /// Invalidates a login token.
+    pub fn logout(&self, token: &str) -> ManagerResult<()> {
+        self.accounts.write().unwrap().logout(token)
+    }
+
+    /// Creates a new private table.
+    ///
+    /// Returns the code players must give to join it.
+    pub fn create_table(&self) -> ManagerResult<TableInfo> {
+        try!(self.check_open());
+        let mut tables = self.tables.lock().unwrap();
+
+        let mut code = make_table_code();
+        while tables.contains_key(&code) {
+            code = make_table_code();
         }
 
-        // println!("Almost ready!");
+        trace!("New table: {}", code);
+        tables.insert(code.clone(), Table::new());
+
+        Ok(TableInfo { code: code })
+    }
+
+    /// Sits at a private table, and waits until all 4 seats are taken.
+    ///
+    /// Like `join`, it returns `Error::NoPartyFound` after the join timeout,
+    /// and frees the seat.
+    pub fn join_table(&self,
+                      code: &str,
+                      options: TableJoinBody,
+                      login: Option<&str>)
+                      -> ManagerResult<NewPartyInfo> {
+        trace!("Join table {}: {:?}", code, options);
+        try!(self.check_open());
+
+        let player = try!(self.make_player(&options.name, login));
+
+        let (seat, ticket, future) = {
+            let mut tables = self.tables.lock().unwrap();
+
+            let seat = match tables.get_mut(code) {
+                Some(table) => {
+                    // Players retrying after a timeout keep the table alive.
+                    table.last_used = time::now();
+                    try!(table.find_seat(&options))
+                }
+                None => return Err(Error::BadTableCode),
+            };
+
+            if tables[code].free_seats() == 1 {
+                // We were the last one missing!
+                let table = tables.remove(code).unwrap();
+                let mut player = Some(player);
+                let seats = table.seats
+                                 .into_iter()
+                                 .map(|seat| {
+                                     match seat {
+                                         Some((_, other, promise)) => Seat::Waiting(other, promise),
+                                         None => Seat::Current(player.take().unwrap()),
+                                     }
+                                 })
+                                 .collect();
+                return Ok(self.start_party(seats));
+            }
+
+            let table = tables.get_mut(code).unwrap();
+            let ticket = table.next_ticket;
+            table.next_ticket += 1;
+            let (promise, future) = Future::pair();
+            table.seats[seat] = Some((ticket, player, promise));
+
+            (seat, ticket, future)
+        };
+
+        match self.await_timeout(future, self.settings.join_timeout) {
+            Timed::Done(info) => Ok(info),
+            Timed::Expired(future) => {
+                let mut tables = self.tables.lock().unwrap();
+
+                // Free our seat, unless the party started meanwhile.
+                let mine = match tables.get(code) {
+                    Some(table) => {
+                        match table.seats[seat] {
+                            Some((t, _, _)) => t == ticket,
+                            None => false,
+                        }
+                    }
+                    None => false,
+                };
+
+                if mine {
+                    trace!("Table join timed out");
+                    let table = tables.get_mut(code).unwrap();
+                    table.seats[seat] = None;
+                    table.last_used = time::now();
+                    Err(Error::NoPartyFound)
+                } else {
+                    // The party is started under the table lock, so this won't block.
+                    future.await().map_err(|_| Error::NoPartyFound)
+                }
+            }
+            Timed::Aborted => {
+                // Tables are dropped when the server stops.
+                try!(self.check_open());
+                Err(Error::NoPartyFound)
+            }
+        }
+    }
 
-        // Even you, weird 4th dude.
-        NewPartyInfo {
-            player_id: ids[3],
-            player_pos: pos::PlayerPos::P3,
+    // Forgets about tables nobody tried to join for a long time.
+    fn remove_unused_tables(&self) {
+        let max_idle = time::Duration::milliseconds(self.settings.idle_timeout as i64);
+        let now = time::now();
+
+        let mut tables = self.tables.lock().unwrap();
+        let unused: Vec<String> = tables.iter()
+                                        .filter(|&(_, table)| {
+                                            table.free_seats() == 4 &&
+                                            now - table.last_used > max_idle
+                                        })
+                                        .map(|(code, _)| code.clone())
+                                        .collect();
+        for code in unused {
+            trace!("Removing unused table: {}", code);
+            tables.remove(&code);
         }
     }
 
     // Play a card in the current game
-    pub fn play_card(&self, player_id: u32, card: CardBody) -> ManagerResult<Event> {
+    pub fn play_card(&self,
+                     player_id: u32,
+                     token: &str,
+                     card: CardBody)
+                     -> ManagerResult<Event> {
         let list = self.party_list.read().unwrap();
-        let info = try!(list.get_player_info(player_id));
+        let info = try!(list.get_player_info(player_id, token));
 
 
         let mut party = info.party.write().unwrap();
-        party.play_card(info.pos, card.card)
-
+        let event = try!(party.play_card(info.pos, card.card));
+        party.play_bots();
+        Ok(event)
     }
 
-    pub fn bid(&self, player_id: u32, contract: ContractBody) -> ManagerResult<Event> {
+    pub fn bid(&self,
+               player_id: u32,
+               token: &str,
+               contract: ContractBody)
+               -> ManagerResult<Event> {
         let list = self.party_list.read().unwrap();
-        let info = try!(list.get_player_info(player_id));
+        let info = try!(list.get_player_info(player_id, token));
 
         let mut party = info.party.write().unwrap();
-        party.bid(info.pos, contract.suit, contract.target)
+        let event = try!(party.bid(info.pos, contract.suit, contract.target));
+        party.play_bots();
+        Ok(event)
     }
 
-    pub fn pass(&self, player_id: u32) -> ManagerResult<Event> {
+    pub fn pass(&self, player_id: u32, token: &str) -> ManagerResult<Event> {
         let list = self.party_list.read().unwrap();
-        let info = try!(list.get_player_info(player_id));
+        let info = try!(list.get_player_info(player_id, token));
 
         let mut party = info.party.write().unwrap();
-        party.pass(info.pos)
+        let event = try!(party.pass(info.pos));
+        party.play_bots();
+        Ok(event)
     }
 
-    pub fn coinche(&self, player_id: u32) -> ManagerResult<Event> {
+    pub fn coinche(&self, player_id: u32, token: &str) -> ManagerResult<Event> {
         let list = self.party_list.read().unwrap();
-        let info = try!(list.get_player_info(player_id));
+        let info = try!(list.get_player_info(player_id, token));
 
         let mut party = info.party.write().unwrap();
-        party.coinche(info.pos)
+        let event = try!(party.coinche(info.pos));
+        party.play_bots();
+        Ok(event)
     }
 
-    pub fn see_hand(&self, player_id: u32) -> ManagerResult<cards::Hand> {
+    /// Runs an action sent on a connection: `bid`, `pass`, `coinche` or `play`.
+    ///
+    /// Returns the event it caused. Leaving is up to the connection, with `leave`.
+    pub fn run_action(&self,
+                      player_id: u32,
+                      token: &str,
+                      action: ActionBody)
+                      -> ManagerResult<Event> {
+        match (&*action.action, action.target, action.suit, action.card) {
+            ("pass", _, _, _) => self.pass(player_id, token),
+            ("coinche", _, _, _) => self.coinche(player_id, token),
+            ("bid", Some(target), Some(suit), _) => {
+                let contract = ContractBody {
+                    target: target,
+                    suit: suit,
+                };
+                self.bid(player_id, token, contract)
+            }
+            ("play", _, _, Some(card)) => self.play_card(player_id, token, CardBody { card: card }),
+            ("bid", _, _, _) => Err(Error::BadAction("bid needs a target and a suit".to_string())),
+            ("play", _, _, _) => Err(Error::BadAction("play needs a card".to_string())),
+            (other, _, _, _) => Err(Error::BadAction(format!("invalid action: `{}`", other))),
+        }
+    }
+
+    pub fn see_hand(&self, player_id: u32, token: &str) -> ManagerResult<cards::Hand> {
         let list = self.party_list.read().unwrap();
-        let info = try!(list.get_player_info(player_id));
+        let info = try!(list.get_player_info(player_id, token));
 
         let party = info.party.read().unwrap();
         let hands = match party.game {
-            Game::Bidding(ref auction) => auction.hands(),
+            Game::Bidding(_, hands, _) => hands,
             Game::Playing(ref game) => game.hands(),
         };
 
         Ok(hands[info.pos as usize])
     }
 
-    pub fn see_trick(&self, player_id: u32) -> ManagerResult<trick::Trick> {
+    pub fn see_trick(&self, player_id: u32, token: &str) -> ManagerResult<trick::Trick> {
         let list = self.party_list.read().unwrap();
-        let info = try!(list.get_player_info(player_id));
+        let info = try!(list.get_player_info(player_id, token));
 
         let party = info.party.read().unwrap();
         let game = try!(party.get_game());
         Ok(game.current_trick().clone())
     }
 
-    pub fn see_last_trick(&self, player_id: u32) -> ManagerResult<trick::Trick> {
+    pub fn see_last_trick(&self,
+                          player_id: u32,
+                          token: &str)
+                          -> ManagerResult<trick::Trick> {
         let list = self.party_list.read().unwrap();
-        let info = try!(list.get_player_info(player_id));
+        let info = try!(list.get_player_info(player_id, token));
 
         let party = info.party.read().unwrap();
         let game = try!(party.get_game());
@@ -470,59 +1619,637 @@ impl GameManager {
         Ok(trick.clone())
     }
 
-    pub fn see_scores(&self, player_id: u32) -> ManagerResult<[i32; 2]> {
+    pub fn see_scores(&self, player_id: u32, token: &str) -> ManagerResult<[i32; 2]> {
         let list = self.party_list.read().unwrap();
-        let info = try!(list.get_player_info(player_id));
+        let info = try!(list.get_player_info(player_id, token));
 
         let party = info.party.read().unwrap();
         Ok(party.scores)
     }
 
-    pub fn see_pos(&self, player_id: u32) -> ManagerResult<pos::PlayerPos> {
+    /// Returns every finished deal of the player's party, oldest first.
+    pub fn see_history(&self, player_id: u32, token: &str) -> ManagerResult<Vec<DealRecord>> {
+        let list = self.party_list.read().unwrap();
+        let info = try!(list.get_player_info(player_id, token));
+
+        let party = info.party.read().unwrap();
+        Ok(party.history.clone())
+    }
+
+    /// Returns a single finished deal of the player's party.
+    ///
+    /// Deals are numbered from 0, in the order they were played.
+    pub fn see_deal(&self,
+                    player_id: u32,
+                    token: &str,
+                    deal_id: usize)
+                    -> ManagerResult<DealRecord> {
+        let list = self.party_list.read().unwrap();
+        let info = try!(list.get_player_info(player_id, token));
+
+        let party = info.party.read().unwrap();
+        party.history.get(deal_id).cloned().ok_or(Error::BadDealId)
+    }
+
+    /// Exports the player's party as a replay.
+    ///
+    /// While the party goes on, only finished deals are included.
+    /// Once it is over, the replay stays available until the idle timeout.
+    pub fn export_replay(&self, player_id: u32, token: &str) -> ManagerResult<Replay> {
+        let list = self.party_list.read().unwrap();
+        let info = try!(list.get_any_player_info(player_id, token));
+
+        let party = info.party.read().unwrap();
+        Ok(party.replay())
+    }
+
+    /// Returns what a player needs to get back in his party after a disconnection.
+    pub fn resume(&self, player_id: u32, token: &str) -> ManagerResult<ResumeInfo> {
         let list = self.party_list.read().unwrap();
-        let info = try!(list.get_player_info(player_id));
+        let info = try!(list.get_player_info(player_id, token));
+
+        trace!("Player resuming: {}", player_id);
+
+        let party = info.party.read().unwrap();
+        let hands = match party.game {
+            Game::Bidding(_, hands, _) => hands,
+            Game::Playing(ref game) => game.hands(),
+        };
+
+        Ok(ResumeInfo {
+            party: NewPartyInfo {
+                player_id: player_id,
+                player_pos: info.pos,
+                names: party.names.clone(),
+                token: info.token.clone(),
+            },
+            hand: hands[info.pos as usize],
+            last_event_id: party.events.len() - 1,
+            deal_event_id: party.deal_start,
+        })
+    }
+
+    pub fn see_pos(&self, player_id: u32, token: &str) -> ManagerResult<pos::PlayerPos> {
+        let list = self.party_list.read().unwrap();
+        let info = try!(list.get_player_info(player_id, token));
         Ok(info.pos)
     }
 
-    // TODO: auto-leave players after long inactivity
-    pub fn leave(&self, player_id: u32) -> ManagerResult<()> {
+    pub fn leave(&self, player_id: u32, token: &str) -> ManagerResult<()> {
         let mut list = self.party_list.write().unwrap();
 
         trace!("Player leaving: {}", player_id);
 
-        try!(list.remove(player_id));
+        try!(list.get_player_info(player_id, token));
+        try!(list.remove(player_id, "player left"));
+
+        Ok(())
+    }
+
+    /// Removes every player inactive for longer than the idle timeout.
+    ///
+    /// Their parties are cancelled. Returns the number of players removed.
+    pub fn remove_inactive(&self) -> usize {
+        let max_idle = time::Duration::milliseconds(self.settings.idle_timeout as i64);
+        let now = time::now();
+
+        let mut list = self.party_list.write().unwrap();
+        let idle: Vec<u32> = list.player_map
+                                 .iter()
+                                 .filter(|&(_, info)| {
+                                     now - *info.last_time.lock().unwrap() > max_idle
+                                 })
+                                 .map(|(&id, _)| id)
+                                 .collect();
+
+        for &player_id in &idle {
+            trace!("Removing inactive player: {}", player_id);
+            // The ID comes from the map, it cannot fail.
+            list.remove(player_id, "player inactive").ok();
+        }
+
+        // Released players had the same time to get their replay.
+        let expired: Vec<u32> = list.released
+                                    .iter()
+                                    .filter(|&(_, info)| {
+                                        now - *info.last_time.lock().unwrap() > max_idle
+                                    })
+                                    .map(|(&id, _)| id)
+                                    .collect();
+        for player_id in expired {
+            list.released.remove(&player_id);
+        }
+
+        let gone: Vec<u32> = list.spectators
+                                 .iter()
+                                 .filter(|&(_, spectator)| {
+                                     now - *spectator.last_time.lock().unwrap() > max_idle
+                                 })
+                                 .map(|(&id, _)| id)
+                                 .collect();
+        for spectator_id in gone {
+            trace!("Removing inactive spectator: {}", spectator_id);
+            list.spectators.remove(&spectator_id);
+        }
+
+        // Nobody can watch a cancelled party anymore.
+        let over: Vec<u32> = list.parties
+                                 .iter()
+                                 .filter(|&(_, party)| party.read().unwrap().cancelled)
+                                 .map(|(&id, _)| id)
+                                 .collect();
+        for party_id in over {
+            list.parties.remove(&party_id);
+        }
+
+        idle.len()
+    }
+
+    /// Starts a thread that regularly removes inactive players.
+    pub fn spawn_reaper(manager: Arc<GameManager>) -> thread::JoinHandle<()> {
+        // Check often enough to respect the timeout within 10%.
+        let period = Duration::from_millis(manager.settings.idle_timeout as u64 / 10 + 1);
+        thread::spawn(move || {
+            loop {
+                thread::sleep(period);
+                manager.remove_inactive();
+                manager.remove_unused_tables();
+            }
+        })
+    }
+
+    // Forget about a player who saw his party being cancelled.
+    fn release_if_cancelled(&self, player_id: u32, events: &[Event]) {
+        let cancelled = events.iter().any(|event| {
+            match event.event {
+                EventType::PartyCancelled(_) => true,
+                _ => false,
+            }
+        });
+
+        if cancelled {
+            trace!("Releasing player: {}", player_id);
+            self.party_list.write().unwrap().release(player_id);
+        }
+    }
+
+    /// Lists the parties going on, so spectators can pick one.
+    pub fn list_parties(&self) -> Vec<PartySummary> {
+        let list = self.party_list.read().unwrap();
+        let mut parties: Vec<PartySummary> = list.parties
+                                                 .iter()
+                                                 .filter_map(|(&id, party)| {
+                                                     let party = party.read().unwrap();
+                                                     if party.cancelled {
+                                                         return None;
+                                                     }
+                                                     Some(PartySummary {
+                                                         party_id: id,
+                                                         names: party.names.clone(),
+                                                         scores: party.scores,
+                                                         deals: party.history.len(),
+                                                     })
+                                                 })
+                                                 .collect();
+        parties.sort_by(|a, b| a.party_id.cmp(&b.party_id));
+        parties
+    }
+
+    /// Starts watching a party.
+    ///
+    /// Spectators can follow the events, but can never act.
+    pub fn spectate(&self, party_id: u32) -> ManagerResult<SpectatorInfo> {
+        try!(self.check_open());
+        let mut list = self.party_list.write().unwrap();
+        let party = try!(list.parties.get(&party_id).cloned().ok_or(Error::BadPartyId));
+
+        let (names, deal_event_id) = {
+            let party = party.read().unwrap();
+            if party.cancelled {
+                return Err(Error::BadPartyId);
+            }
+            (party.names.clone(), party.deal_start)
+        };
+
+        let spectator_id = list.make_spectator_id();
+        trace!("New spectator for party {}: {}", party_id, spectator_id);
+        list.spectators.insert(spectator_id,
+                               SpectatorEntry {
+                                   party: party,
+                                   last_time: Mutex::new(time::now()),
+                               });
+
+        Ok(SpectatorInfo {
+            spectator_id: spectator_id,
+            names: names,
+            deal_event_id: deal_event_id,
+            delay: self.settings.spectator_delay,
+        })
+    }
+
+    /// Returns every event a spectator can see from `event_id` on.
+    ///
+    /// Like `wait_events`, blocks if none is visible yet, and returns
+    /// `Error::WaitTimeout` after the wait timeout.
+    pub fn watch(&self, spectator_id: u32, event_id: usize) -> ManagerResult<Vec<Event>> {
+        let wait_timeout = self.settings.wait_timeout;
+
+        let delay = match try!(self.spectator_events(spectator_id, event_id, true)) {
+            SpectatorWait::Ready(events) => return Ok(events),
+            SpectatorWait::Delayed(remaining) => cmp::min(remaining, wait_timeout),
+            SpectatorWait::Waiting(party, ticket, future) => {
+                match self.await_timeout(future, wait_timeout) {
+                    Timed::Done(_) => self.settings.spectator_delay.unwrap_or(0),
+                    Timed::Expired(_) | Timed::Aborted => {
+                        // Stop observing, so we don't leave a dead promise behind.
+                        party.read().unwrap().observers.lock().unwrap().remove(ticket);
+                        return Err(Error::WaitTimeout);
+                    }
+                }
+            }
+        };
+
+        if delay >= wait_timeout {
+            // The spectator will be back for it.
+            return Err(Error::WaitTimeout);
+        }
+        thread::sleep(Duration::from_millis(delay as u64));
+
+        match try!(self.spectator_events(spectator_id, event_id, false)) {
+            SpectatorWait::Ready(ref events) if !events.is_empty() => Ok(events.clone()),
+            _ => Err(Error::WaitTimeout),
+        }
+    }
+
+    // Returns the events a spectator can see from `event_id`.
+    // If there is none and `observe` is true, starts observing the party.
+    fn spectator_events(&self,
+                        spectator_id: u32,
+                        event_id: usize,
+                        observe: bool)
+                        -> ManagerResult<SpectatorWait> {
+        let list = self.party_list.read().unwrap();
+        let spectator = try!(list.get_spectator(spectator_id));
+        let party = spectator.party.read().unwrap();
+
+        if event_id > party.events.len() {
+            return Err(Error::BadEventId);
+        }
+
+        let reveal = self.settings.spectator_delay.is_some();
+        let delay = time::Duration::milliseconds(self.settings.spectator_delay.unwrap_or(0) as i64);
+        let now = time::now();
+
+        let events: Vec<Event> = party.events
+                                      .iter()
+                                      .zip(&party.times)
+                                      .enumerate()
+                                      .skip(event_id)
+                                      .take_while(|&(_, (_, time))| now - *time >= delay)
+                                      .map(|(id, (event, _))| {
+                                          Event {
+                                              event: event.spectate(reveal),
+                                              id: id,
+                                          }
+                                      })
+                                      .collect();
+
+        if !events.is_empty() || !observe {
+            return Ok(SpectatorWait::Ready(events));
+        }
+
+        if event_id < party.events.len() {
+            let remaining = delay - (now - party.times[event_id]);
+            return Ok(SpectatorWait::Delayed(remaining.num_milliseconds() as u32));
+        }
+
+        let (ticket, future) = party.observers.lock().unwrap().push(());
+        Ok(SpectatorWait::Waiting(spectator.party.clone(), ticket, future))
+    }
+
+    // Refuses new players once the server is shutting down.
+    fn check_open(&self) -> ManagerResult<()> {
+        if self.closing.load(Ordering::SeqCst) {
+            Err(Error::ShuttingDown)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Stops the server gracefully.
+    ///
+    /// New players are refused, and players waiting for a party get an
+    /// `Error::ShuttingDown`. Every party is cancelled with the given message,
+    /// which also answers pending waits. Saved parties stay on disk, to be
+    /// resumed after a restart.
+    ///
+    /// Returns after a short delay, to let the answers reach the clients.
+    pub fn shutdown(&self, msg: &str) {
+        if self.closing.swap(true, Ordering::SeqCst) {
+            return;
+        }
+
+        // Dropping the promises wakes up whoever waits for a party.
+        let requests = self.waiting_list.lock().unwrap().drain();
+        trace!("Dropping {} join requests", requests.len());
+        drop(requests);
+        self.tables.lock().unwrap().clear();
+
+        {
+            let list = self.party_list.read().unwrap();
+            for party in list.parties.values() {
+                party.write().unwrap().shut_down(msg.to_string());
+            }
+        }
+
+        thread::sleep(Duration::from_millis(SHUTDOWN_DELAY));
+    }
+
+    /// Returns an overview of the server, for the admin.
+    pub fn status(&self) -> ServerStatus {
+        // Joins lock the waiting list and the tables before the party list:
+        // don't hold the party list while locking them.
+        let waiting_list = self.waiting_list.lock().unwrap().len();
+        let tables = self.tables.lock().unwrap().len();
+
+        let list = self.party_list.read().unwrap();
+        let parties = list.parties
+                          .values()
+                          .filter(|party| !party.read().unwrap().cancelled)
+                          .count();
+
+        ServerStatus {
+            waiting_list: waiting_list,
+            tables: tables,
+            parties: parties,
+            players: list.player_map.len(),
+            spectators: list.spectators.len(),
+        }
+    }
+
+    /// Describes every party, with its seats, for the admin.
+    pub fn party_statuses(&self) -> Vec<PartyStatus> {
+        let now = time::now();
+        let list = self.party_list.read().unwrap();
+
+        // Players who left are not in the list anymore.
+        let humans: Vec<(u32, u32, pos::PlayerPos, i64, Option<String>)> =
+            list.player_map
+                .iter()
+                .map(|(&player_id, info)| {
+                    let idle = now - *info.last_time.lock().unwrap();
+                    (info.party.read().unwrap().id,
+                     player_id,
+                     info.pos,
+                     idle.num_seconds(),
+                     info.account.clone())
+                })
+                .collect();
+
+        let mut result = Vec::new();
+        for (&id, party) in &list.parties {
+            let party = party.read().unwrap();
+            let mut seats: Vec<SeatStatus> = (0..4)
+                                                 .map(|i| {
+                                                     SeatStatus {
+                                                         pos: pos::PlayerPos::from_n(i),
+                                                         name: party.names[i].clone(),
+                                                         bot: party.bots[i],
+                                                         player_id: None,
+                                                         account: None,
+                                                         idle: None,
+                                                     }
+                                                 })
+                                                 .collect();
+
+            for &(party_id, player_id, pos, idle, ref account) in &humans {
+                if party_id == id {
+                    seats[pos as usize].player_id = Some(player_id);
+                    seats[pos as usize].account = account.clone();
+                    seats[pos as usize].idle = Some(idle);
+                }
+            }
+
+            result.push(PartyStatus {
+                party_id: id,
+                phase: party.phase().to_string(),
+                scores: party.scores,
+                deals: party.history.len(),
+                events: party.events.len(),
+                seats: seats,
+            });
+        }
+
+        result.sort_by(|a, b| a.party_id.cmp(&b.party_id));
+        result
+    }
+
+    /// Describes a single party, for the admin.
+    pub fn party_status(&self, party_id: u32) -> ManagerResult<PartyStatus> {
+        self.party_statuses()
+            .into_iter()
+            .find(|party| party.party_id == party_id)
+            .ok_or(Error::BadPartyId)
+    }
+
+    /// Returns every event of a party, hands included, for the admin.
+    pub fn party_events(&self, party_id: u32) -> ManagerResult<Vec<TimedEvent>> {
+        let list = self.party_list.read().unwrap();
+        let party = try!(list.parties.get(&party_id).ok_or(Error::BadPartyId));
+
+        let party = party.read().unwrap();
+        Ok(party.events
+                .iter()
+                .zip(&party.times)
+                .map(|(event, time)| TimedEvent::new(event.clone(), time))
+                .collect())
+    }
+
+    /// Cancels a party on behalf of the admin.
+    ///
+    /// Its players get a PartyCancelled event with the given message.
+    pub fn cancel_party(&self, party_id: u32, msg: Option<String>) -> ManagerResult<()> {
+        let list = self.party_list.read().unwrap();
+        let party = try!(list.parties.get(&party_id).ok_or(Error::BadPartyId));
+
+        let mut party = party.write().unwrap();
+        if party.cancelled {
+            return Err(Error::BadPartyId);
+        }
+        trace!("Cancelling party {}", party_id);
+        party.cancel(msg.unwrap_or("cancelled by the server".to_string()));
 
         Ok(())
     }
 
-    // Waits until the given event_id happens
-    pub fn wait(&self, player_id: u32, event_id: usize) -> ManagerResult<Event> {
-        let res = try!(self.get_wait_result(player_id, event_id));
+    /// Removes a player on behalf of the admin, cancelling his party.
+    pub fn kick(&self, player_id: u32) -> ManagerResult<()> {
+        let mut list = self.party_list.write().unwrap();
 
-        // TODO: add a timeout (~15s?)
+        trace!("Kicking player: {}", player_id);
+        list.remove(player_id, "player kicked")
+    }
+
+    /// Waits until the given event_id happens.
+    ///
+    /// Returns `Error::WaitTimeout` if nothing happened before the wait timeout.
+    /// The client should then try again with the same event id.
+    pub fn wait(&self, player_id: u32, token: &str, event_id: usize) -> ManagerResult<Event> {
+        let event = try!(self.wait_event(player_id, token, event_id, true));
+        self.release_if_cancelled(player_id, &[event.clone()]);
+        Ok(event)
+    }
+
+    fn wait_event(&self,
+                  player_id: u32,
+                  token: &str,
+                  event_id: usize,
+                  your_turn: bool)
+                  -> ManagerResult<Event> {
+        let (res, party) = try!(self.get_wait_result(player_id, token, event_id, your_turn));
 
         match res {
             Ready(event) => Ok(event),
-            // TODO: handle case where the wait is cancelled
-            // (don't unwrap, return an error instead?)
-            Waiting(future) => Ok(future.await().unwrap()),
+            Waiting(ticket, future) => {
+                match self.await_timeout(future, self.settings.wait_timeout) {
+                    Timed::Done(event) => Ok(event),
+                    Timed::Expired(future) => {
+                        // Stop observing, so we don't leave a dead promise behind.
+                        let party = party.read().unwrap();
+                        if party.observers.lock().unwrap().remove(ticket).is_some() {
+                            Err(Error::WaitTimeout)
+                        } else {
+                            // The event happened while we timed out.
+                            future.await().map_err(|_| Error::WaitTimeout)
+                        }
+                    }
+                    Timed::Aborted => Err(Error::WaitTimeout),
+                }
+            }
+        }
+    }
+
+    /// Returns every event from `event_id` to the latest one.
+    ///
+    /// Only blocks (like `wait`) if no such event happened yet.
+    pub fn wait_events(&self,
+                       player_id: u32,
+                       token: &str,
+                       event_id: usize)
+                       -> ManagerResult<Vec<Event>> {
+        self.stream_events(player_id, token, event_id, true)
+    }
+
+    /// Gives every event from `event_id` to `sink`, as they happen.
+    ///
+    /// This is for connections pushing events to the player: `YourTurn` is only
+    /// given once per turn. `sink` also gets `None` whenever the wait timeout
+    /// expires without a new event, and returns `false` to stop.
+    ///
+    /// Returns once the party is cancelled or `sink` stopped, or with the error
+    /// that interrupted the events.
+    pub fn push_events<F>(&self,
+                          player_id: u32,
+                          token: &str,
+                          mut event_id: usize,
+                          mut sink: F)
+                          -> ManagerResult<()>
+        where F: FnMut(Option<Event>) -> bool
+    {
+        // Only tell the player once that it's his turn.
+        let mut your_turn = true;
+
+        loop {
+            let events = match self.stream_events(player_id, token, event_id, your_turn) {
+                Ok(events) => events,
+                Err(Error::WaitTimeout) => {
+                    if !sink(None) {
+                        return Ok(());
+                    }
+                    continue;
+                }
+                Err(err) => return Err(err),
+            };
+
+            for event in events {
+                let over = match event.event {
+                    EventType::YourTurn => {
+                        your_turn = false;
+                        false
+                    }
+                    EventType::PartyCancelled(_) => true,
+                    _ => {
+                        your_turn = true;
+                        event_id = event.id + 1;
+                        false
+                    }
+                };
+
+                if !sink(Some(event)) || over {
+                    return Ok(());
+                }
+            }
+        }
+    }
+
+    // Like `wait_events`, but if `your_turn` is false, a YourTurn event is
+    // never returned: it blocks until an actual event happens instead.
+    fn stream_events(&self,
+                     player_id: u32,
+                     token: &str,
+                     event_id: usize,
+                     your_turn: bool)
+                     -> ManagerResult<Vec<Event>> {
+        let events: Vec<Event> = {
+            let list = self.party_list.read().unwrap();
+            let info = try!(list.get_player_info(player_id, token));
+
+            let party = info.party.read().unwrap();
+            party.events
+                 .iter()
+                 .enumerate()
+                 .skip(event_id)
+                 .map(|(id, event)| {
+                     Event {
+                         event: event.relativize(info.pos),
+                         id: id,
+                     }
+                 })
+                 .collect()
+        };
+
+        if events.is_empty() {
+            // Nothing to catch up: wait for the next one.
+            let event = try!(self.wait_event(player_id, token, event_id, your_turn));
+            self.release_if_cancelled(player_id, &[event.clone()]);
+            Ok(vec![event])
+        } else {
+            self.release_if_cancelled(player_id, &events);
+            Ok(events)
         }
     }
 
     // Check if the event ID is already available.
     // If not, returns a channel that will produce it one
     // day, so that we don't keep the locks while waiting.
-    fn get_wait_result(&self, player_id: u32, event_id: usize) -> ManagerResult<WaitResult> {
+    // Also returns the party, to stop observing it on timeout.
+    fn get_wait_result(&self,
+                       player_id: u32,
+                       token: &str,
+                       event_id: usize,
+                       your_turn: bool)
+                       -> ManagerResult<(WaitResult, Arc<RwLock<Party>>)> {
         let list = self.party_list.read().unwrap();
-        let info = try!(list.get_player_info(player_id));
+        let info = try!(list.get_player_info(player_id, token));
 
         let party = info.party.read().unwrap();
 
         if party.events.len() > event_id {
-            return Ok(Ready(Event {
+            return Ok((Ready(Event {
                 event: party.events[event_id].relativize(info.pos),
                 id: event_id,
-            }));
+            }),
+                       info.party.clone()));
         } else if event_id > party.events.len() {
             // We are too ambitious! One event at a time!
             return Err(Error::BadEventId);
@@ -530,17 +2257,17 @@ impl GameManager {
 
         // Ok, so we'll have to wait a bit.
         // ... maybe?
-        if info.pos == party.game.next_player() {
+        if your_turn && info.pos == party.game.next_player() {
             // If we're actually waiting for this guy, tell him!
-            return Ok(Ready(Event {
+            return Ok((Ready(Event {
                 event: EventType::YourTurn,
                 id: event_id - 1,
-            }));
+            }),
+                       info.party.clone()));
         }
 
-        let (promise, future) = Future::pair();
-        party.observers.lock().unwrap().push(promise);
+        let (ticket, future) = party.observers.lock().unwrap().push(());
 
-        Ok(Waiting(future))
+        Ok((Waiting(ticket, future), info.party.clone()))
     }
 }
@@ -1,17 +1,66 @@
 
-use super::game_manager::GameManager;
-use {ContractBody, CardBody, Error};
-
+use super::game_manager::{GameManager, Settings};
+use super::config::{Config, DEFAULT_PORT};
+use super::error;
+use super::websocket;
+use super::tcp;
+use {JoinBody, TableJoinBody, AccountBody, ContractBody, CardBody, CancelBody, Error, Event};
+
+use std::io::{self, Write};
 use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::str::FromStr;
+use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4};
+use std::path::PathBuf;
 
 use rustc_serialize::json;
 use iron::prelude::*;
 use iron;
+use iron::response::{ResponseBody, WriteBody};
 use bodyparser;
+use url;
+use num_cpus;
+
+// The WebSocket interface only speaks plain `ws://`: it would leak the tokens
+// of players using HTTPS.
+const NO_WSS: &'static str = "the WebSocket interface does not support TLS: \
+                              use either --ws-port or --cert and --key";
+
+// Same for the TCP interface, which only speaks plain TCP.
+const NO_TCP_TLS: &'static str = "the TCP interface does not support TLS: \
+                                  use either --tcp-port or --cert and --key";
 
 struct Router {
     manager: Arc<GameManager>,
+    // Event streams currently open on this address
+    streams: Arc<AtomicUsize>,
+}
+
+// Body of a `text/event-stream` response, sending events as they happen.
+//
+// It keeps an iron worker thread busy as long as it's open.
+struct EventStream {
+    manager: Arc<GameManager>,
+    player_id: u32,
+    token: String,
+    event_id: usize,
+    // Counter of open streams, decremented when this one is dropped
+    streams: Arc<AtomicUsize>,
+}
+
+// Maximum number of event streams open at once, on each address.
+//
+// Iron serves each address with `8 * cores` threads: leave at least half of
+// them to the other requests.
+fn max_streams() -> usize {
+    4 * num_cpus::get()
+}
+
+// Serves the admin interface, on its own port.
+struct AdminRouter {
+    manager: Arc<GameManager>,
+    // Token expected from the admin, if any
+    secret: Option<String>,
 }
 
 #[derive(RustcEncodable)]
@@ -24,11 +73,21 @@ struct HelpAction {
 #[derive(RustcEncodable)]
 struct HelpMessage {
     title: &'static str,
+    auth: &'static str,
     actions: Vec<HelpAction>,
 }
 
 pub struct Server {
-    port: u16,
+    addresses: Vec<SocketAddr>,
+    // Certificate and private key files, to serve HTTPS
+    tls: Option<(PathBuf, PathBuf)>,
+    // Port of the admin interface, if any
+    admin_port: Option<u16>,
+    admin_secret: Option<String>,
+    // Port of the WebSocket interface, if any
+    ws_port: Option<u16>,
+    // Port of the TCP interface, if any
+    tcp_port: Option<u16>,
     manager: Arc<GameManager>,
 }
 
@@ -36,17 +95,52 @@ fn help_message() -> String {
 
     json::encode(&HelpMessage {
         title: "Help Page",
+        auth: "Requests with a [PLAYER_ID] need the token given when joining, in an \
+               `Authorization: Bearer [TOKEN]` header.",
         actions: vec![
+            HelpAction {
+                href: "/register",
+                method: "POST",
+                help: "Register a new player, with {\"name\": NAME, \"password\": PASSWORD}.",
+            },
+            HelpAction {
+                href: "/login",
+                method: "POST",
+                help: "Log in, with {\"name\": NAME, \"password\": PASSWORD}. Send the \
+                       returned token when joining, to play under your name.",
+            },
+            HelpAction {
+                href: "/logout",
+                method: "POST",
+                help: "Invalidate the login token.",
+            },
             HelpAction {
                 href: "/join",
                 method: "POST",
-                help: "Join a new game.",
+                help: "Join a new game. Empty seats can be given to bots with \
+                       {\"bots_after\": SECONDS}.",
+            },
+            HelpAction {
+                href: "/table",
+                method: "POST",
+                help: "Create a private table. Returns the code to join it.",
+            },
+            HelpAction {
+                href: "/table/[CODE]",
+                method: "POST",
+                help: "Sit at a private table. A seat or a team can be chosen with \
+                       {\"seat\": POS} or {\"team\": TEAM}.",
             },
             HelpAction {
                 href: "/leave/[PLAYER_ID]",
                 method: "POST",
                 help: "Leave the current game.",
             },
+            HelpAction {
+                href: "/resume/[PLAYER_ID]",
+                method: "POST",
+                help: "Get back in the current game after a disconnection.",
+            },
             HelpAction {
                 href: "/pass/[PLAYER_ID]",
                 method: "POST",
@@ -92,11 +186,93 @@ fn help_message() -> String {
                 method: "GET",
                 help: "Get the player's position on the table.",
             },
+            HelpAction {
+                href: "/history/[PLAYER_ID]",
+                method: "GET",
+                help: "Get every finished deal of the party.",
+            },
+            HelpAction {
+                href: "/history/[PLAYER_ID]/[DEAL_ID]",
+                method: "GET",
+                help: "Get a single finished deal, numbered from 0.",
+            },
+            HelpAction {
+                href: "/replay/[PLAYER_ID]",
+                method: "GET",
+                help: "Export the party as a replay file. Available until the idle timeout \
+                       once the party is over.",
+            },
             HelpAction {
                 href: "/wait/[PLAYER_ID]/[EVENT_ID]",
                 method: "GET",
                 help: "Wait until the next event, or return it if it already happened.",
             },
+            HelpAction {
+                href: "/events/[PLAYER_ID]/[EVENT_ID]",
+                method: "GET",
+                help: "Get every event since the given one, or wait for the next one.",
+            },
+            HelpAction {
+                href: "/stream/[PLAYER_ID]/[EVENT_ID]",
+                method: "GET",
+                help: "Stream every event from the given one, as Server-Sent Events. Clients \
+                       that cannot set headers may give the token as a `token` query \
+                       parameter, but URLs are often logged: prefer the header.",
+            },
+            HelpAction {
+                href: "/parties",
+                method: "GET",
+                help: "List the parties going on.",
+            },
+            HelpAction {
+                href: "/spectate/[PARTY_ID]",
+                method: "POST",
+                help: "Watch a party without taking a seat. Returns a spectator ID.",
+            },
+            HelpAction {
+                href: "/watch/[SPECTATOR_ID]/[EVENT_ID]",
+                method: "GET",
+                help: "Get every visible event since the given one, or wait for the next one. \
+                       Spectators see the hands only if the server delays their events.",
+            },
+        ],
+    })
+        .unwrap()
+}
+
+fn admin_help_message() -> String {
+
+    json::encode(&HelpMessage {
+        title: "Admin Help Page",
+        auth: "Only reachable from the server host. If the server has an admin secret, send it \
+               in an `Authorization: Bearer [SECRET]` header.",
+        actions: vec![
+            HelpAction {
+                href: "/status",
+                method: "GET",
+                help: "Get the number of players waiting, parties, players and spectators.",
+            },
+            HelpAction {
+                href: "/parties",
+                method: "GET",
+                help: "List every party, with its seats, phase, scores and idle times.",
+            },
+            HelpAction {
+                href: "/events/[PARTY_ID]",
+                method: "GET",
+                help: "Get every event of a party, with every hand.",
+            },
+            HelpAction {
+                href: "/cancel/[PARTY_ID]",
+                method: "POST",
+                help: "Cancel a party. A message for the players can be given with \
+                       {\"msg\": MESSAGE}.",
+            },
+            HelpAction {
+                href: "/kick/[PLAYER_ID]",
+                method: "POST",
+                help: "Remove a player, cancelling his party.",
+            },
         ],
     })
         .unwrap()
@@ -108,12 +284,84 @@ fn help_resp() -> IronResult<Response> {
     return Ok(Response::with((content_type, iron::status::NotFound, help_message())));
 }
 
+fn admin_help_resp() -> IronResult<Response> {
+    let content_type: iron::mime::Mime = "application/json".parse::<iron::mime::Mime>().unwrap();
+    return Ok(Response::with((content_type, iron::status::NotFound, admin_help_message())));
+}
+
 fn err_resp<S: ToString>(msg: S) -> IronResult<Response> {
     let content_type: iron::mime::Mime = "application/json".parse::<iron::mime::Mime>().unwrap();
 
     return Ok(Response::with((content_type,
                               iron::status::Ok,
-                              json::encode(&Error { error: msg.to_string() }).unwrap())));
+                              json::encode(&Error {
+                                  error: msg.to_string(),
+                                  code: None,
+                              })
+                                  .unwrap())));
+}
+
+fn manager_err_resp(err: &error::Error) -> IronResult<Response> {
+    let content_type: iron::mime::Mime = "application/json".parse::<iron::mime::Mime>().unwrap();
+
+    return Ok(Response::with((content_type,
+                              iron::status::Ok,
+                              json::encode(&Error {
+                                  error: err.to_string(),
+                                  code: Some(err.code().to_string()),
+                              })
+                                  .unwrap())));
+}
+
+// Reads the token from the `Authorization: Bearer <token>` header.
+fn read_token(req: &Request) -> Option<String> {
+    let value = match req.headers.get_raw("Authorization") {
+        Some(values) if values.len() == 1 => values[0].clone(),
+        _ => return None,
+    };
+
+    match String::from_utf8(value) {
+        Ok(ref value) if value.starts_with("Bearer ") => Some(value[7..].trim().to_string()),
+        _ => None,
+    }
+}
+
+// Reads the token from the header, or from a `token` query parameter.
+//
+// Browsers cannot set headers on an `EventSource`. The query is a last resort:
+// URLs are often logged, leaking the token.
+fn read_stream_token(req: &Request) -> Option<String> {
+    read_token(req).or_else(|| {
+        req.url.query.as_ref().and_then(|query| {
+            url::form_urlencoded::parse(query.as_bytes())
+                .into_iter()
+                .find(|&(ref key, _)| key == "token")
+                .map(|(_, value)| value)
+        })
+    })
+}
+
+// Reads the ID of the last event the client received, when it reconnects.
+fn read_last_event_id(req: &Request) -> Option<usize> {
+    match req.headers.get_raw("Last-Event-ID") {
+        Some(values) if values.len() == 1 => {
+            String::from_utf8(values[0].clone())
+                .ok()
+                .and_then(|value| usize::from_str(value.trim()).ok())
+        }
+        _ => None,
+    }
+}
+
+macro_rules! read_token {
+    ( $req:expr ) => {
+        {
+            match read_token($req) {
+                Some(token) => token,
+                None => return manager_err_resp(&error::Error::BadToken),
+            }
+        }
+    };
 }
 
 macro_rules! parse_id {
@@ -158,7 +406,7 @@ macro_rules! my_try {
 
         {
             match $x {
-                Err(err) => return err_resp(format!("{}", err)),
+                Err(err) => return manager_err_resp(&err),
                 Ok(thing) => thing,
             }
         }
@@ -183,6 +431,50 @@ macro_rules! read_body {
     };
 }
 
+impl EventStream {
+    // Writes a single event, as a Server-Sent Event.
+    //
+    // Its ID lets the client resume with `Last-Event-ID`.
+    fn send(res: &mut ResponseBody, event: &Event) -> io::Result<()> {
+        try!(write!(res, "id: {}\ndata: {}\n\n", event.id, json::encode(event).unwrap()));
+        res.flush()
+    }
+}
+
+impl Drop for EventStream {
+    fn drop(&mut self) {
+        self.streams.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+impl WriteBody for EventStream {
+    fn write_body(&mut self, res: &mut ResponseBody) -> io::Result<()> {
+        // Error writing to the client, if any
+        let mut written = Ok(());
+        let result = self.manager.push_events(self.player_id, &self.token, self.event_id, |event| {
+            written = match event {
+                Some(event) => EventStream::send(res, &event),
+                None => {
+                    // A comment keeps proxies from closing the connection.
+                    res.write_all(b": keep-alive\n\n").and_then(|_| res.flush())
+                }
+            };
+            written.is_ok()
+        });
+        try!(written);
+
+        if let Err(err) = result {
+            let error = Error {
+                error: err.to_string(),
+                code: Some(err.code().to_string()),
+            };
+            try!(write!(res, "event: error\ndata: {}\n\n", json::encode(&error).unwrap()));
+            try!(res.flush());
+        }
+        Ok(())
+    }
+}
+
 impl iron::Handler for Router {
     fn handle(&self, req: &mut Request) -> IronResult<Response> {
         trace!("Router::handle()");
@@ -204,12 +496,15 @@ impl iron::Handler for Router {
         match req.method {
             iron::method::Options => {
                 let action = &*req.url.path[0];
-                if ["hand", "trick", "last_trick", "scores", "pos"].contains(&action) {
+                if ["hand", "trick", "last_trick", "scores", "pos", "events", "stream",
+                    "history", "replay", "parties", "watch"]
+                       .contains(&action) {
                     Ok(Response::with((iron::modifiers::Header(iron::headers::Allow(vec![
                                                    iron::method::Get,
                                                    iron::method::Options])),
                                        iron::status::Ok)))
-                } else if ["pass", "coinche", "bid", "play", "join", "leave"].contains(&action) {
+                } else if ["pass", "coinche", "bid", "play", "join", "table", "leave", "resume",
+                            "register", "login", "logout", "spectate"].contains(&action) {
                     Ok(Response::with((iron::modifiers::Header(iron::headers::Allow(vec![
                                                    iron::method::Post,
                                                    iron::method::Options])),
@@ -223,39 +518,129 @@ impl iron::Handler for Router {
                     "wait" => {
                         check_len!(req.url.path, 3);
                         let player_id = parse_id!("player", &*req.url.path[1]);
+                        let token = read_token!(req);
                         let event_id = parse_id!("event", &*req.url.path[2]) as usize;
                         // Result is an Event
-                        try_manager!(self.manager.wait(player_id, event_id))
+                        try_manager!(self.manager.wait(player_id, &token, event_id))
+                    }
+                    "events" => {
+                        check_len!(req.url.path, 3);
+                        let player_id = parse_id!("player", &*req.url.path[1]);
+                        let token = read_token!(req);
+                        let event_id = parse_id!("event", &*req.url.path[2]) as usize;
+                        // Result is a list of Events
+                        try_manager!(self.manager.wait_events(player_id, &token, event_id))
+                    }
+                    "stream" => {
+                        check_len!(req.url.path, 3);
+                        let player_id = parse_id!("player", &*req.url.path[1]);
+                        let token = match read_stream_token(req) {
+                            Some(token) => token,
+                            None => return manager_err_resp(&error::Error::BadToken),
+                        };
+                        // A reconnecting client resumes after the last event it got.
+                        let event_id = match read_last_event_id(req) {
+                            Some(last_id) => last_id + 1,
+                            None => parse_id!("event", &*req.url.path[2]) as usize,
+                        };
+                        my_try!(self.manager.see_pos(player_id, &token));
+
+                        if self.streams.fetch_add(1, Ordering::SeqCst) >= max_streams() {
+                            self.streams.fetch_sub(1, Ordering::SeqCst);
+                            return manager_err_resp(&error::Error::TooManyStreams);
+                        }
+                        // From now on, dropping the stream releases its slot.
+                        let stream = EventStream {
+                            manager: self.manager.clone(),
+                            player_id: player_id,
+                            token: token,
+                            event_id: event_id,
+                            streams: self.streams.clone(),
+                        };
+                        let event_stream = "text/event-stream".parse::<iron::mime::Mime>().unwrap();
+                        let no_cache = iron::headers::CacheControl(vec![
+                            iron::headers::CacheDirective::NoCache,
+                        ]);
+                        let mut response = Response::with((event_stream,
+                                                           iron::status::Ok,
+                                                           iron::modifiers::Header(no_cache)));
+                        response.body = Some(Box::new(stream));
+                        return Ok(response);
                     }
                     "hand" => {
                         check_len!(req.url.path, 2);
                         let player_id = parse_id!("player", &*req.url.path[1]);
+                        let token = read_token!(req);
                         // Result is a cards::Hand = u32
-                        try_manager!(self.manager.see_hand(player_id))
+                        try_manager!(self.manager.see_hand(player_id, &token))
                     }
                     "trick" => {
                         check_len!(req.url.path, 2);
                         let player_id = parse_id!("player", &*req.url.path[1]);
+                        let token = read_token!(req);
                         // Result is a trick::Trick
-                        try_manager!(self.manager.see_trick(player_id))
+                        try_manager!(self.manager.see_trick(player_id, &token))
                     }
                     "last_trick" => {
                         check_len!(req.url.path, 2);
                         let player_id = parse_id!("player", &*req.url.path[1]);
+                        let token = read_token!(req);
                         // Result is a trick::Trick
-                        try_manager!(self.manager.see_last_trick(player_id))
+                        try_manager!(self.manager.see_last_trick(player_id, &token))
                     }
                     "scores" => {
                         check_len!(req.url.path, 2);
                         let player_id = parse_id!("player", &*req.url.path[1]);
+                        let token = read_token!(req);
                         // Result is a [i32; 2]
-                        try_manager!(self.manager.see_scores(player_id))
+                        try_manager!(self.manager.see_scores(player_id, &token))
                     }
                     "pos" => {
                         check_len!(req.url.path, 2);
                         let player_id = parse_id!("player", &*req.url.path[1]);
+                        let token = read_token!(req);
                         // Result is a pos::PlayerPos = usize
-                        try_manager!(self.manager.see_pos(player_id))
+                        try_manager!(self.manager.see_pos(player_id, &token))
+                    }
+                    "replay" => {
+                        check_len!(req.url.path, 2);
+                        let player_id = parse_id!("player", &*req.url.path[1]);
+                        let token = read_token!(req);
+                        // Result is a Replay
+                        try_manager!(self.manager.export_replay(player_id, &token))
+                    }
+                    "parties" => {
+                        check_len!(req.url.path, 1);
+                        // Result is a list of PartySummaries
+                        json::encode(&self.manager.list_parties()).unwrap()
+                    }
+                    "watch" => {
+                        check_len!(req.url.path, 3);
+                        let spectator_id = parse_id!("spectator", &*req.url.path[1]);
+                        let event_id = parse_id!("event", &*req.url.path[2]) as usize;
+                        // Result is a list of Events
+                        try_manager!(self.manager.watch(spectator_id, event_id))
+                    }
+                    "history" => {
+                        match req.url.path.len() {
+                            2 => {
+                                let player_id = parse_id!("player", &*req.url.path[1]);
+                                let token = read_token!(req);
+                                // Result is a list of DealRecords
+                                try_manager!(self.manager.see_history(player_id, &token))
+                            }
+                            3 => {
+                                let player_id = parse_id!("player", &*req.url.path[1]);
+                                let token = read_token!(req);
+                                let deal_id = parse_id!("deal", &*req.url.path[2]) as usize;
+                                // Result is a DealRecord
+                                try_manager!(self.manager.see_deal(player_id, &token, deal_id))
+                            }
+                            _ => {
+                                return err_resp("incorrect parameters (Usage: /history/[PID] \
+                                                 or /history/[PID]/[DID])")
+                            }
+                        }
                     }
                     _ => {
                         trace!("Requesting invalid path: GET {:?}", &req.url.path);
@@ -273,32 +658,105 @@ impl iron::Handler for Router {
                 let response = match &*req.url.path[0] {
                     "join" => {
                         check_len!(req.url.path, 1);
+                        // Options are... optional.
+                        let options = match req.get::<bodyparser::Struct<JoinBody>>() {
+                            Ok(Some(options)) => options,
+                            Ok(None) => JoinBody::default(),
+                            Err(err) => {
+                                return err_resp(format!("Error parsing join options: {:?}", err))
+                            }
+                        };
+                        // Logged in players send their token
+                        let login = read_token(req);
                         // Result is a NewPartyInfo
-                        try_manager!(self.manager.join())
+                        try_manager!(self.manager.join(options, login.as_ref().map(|t| &**t)))
+                    }
+                    "table" => {
+                        match req.url.path.len() {
+                            // Result is a TableInfo
+                            1 => try_manager!(self.manager.create_table()),
+                            2 => {
+                                let code = req.url.path[1].clone();
+                                let options = match req.get::<bodyparser::Struct<TableJoinBody>>() {
+                                    Ok(Some(options)) => options,
+                                    Ok(None) => TableJoinBody::default(),
+                                    Err(err) => {
+                                        return err_resp(format!("Error parsing table options: {:?}",
+                                                                err))
+                                    }
+                                };
+                                let login = read_token(req);
+                                // Result is a NewPartyInfo
+                                try_manager!(self.manager.join_table(&code,
+                                                                     options,
+                                                                     login.as_ref().map(|t| &**t)))
+                            }
+                            _ => {
+                                return err_resp("incorrect parameters (Usage: /table or \
+                                                 /table/[CODE])")
+                            }
+                        }
+                    }
+                    "register" => {
+                        check_len!(req.url.path, 1);
+                        let account = read_body!(req.get::<bodyparser::Struct<AccountBody>>(),
+                                                 "account");
+                        my_try!(self.manager.register(account));
+                        r#""ok""#.to_string()
+                    }
+                    "login" => {
+                        check_len!(req.url.path, 1);
+                        let account = read_body!(req.get::<bodyparser::Struct<AccountBody>>(),
+                                                 "account");
+                        // Result is a LoginInfo
+                        try_manager!(self.manager.login(account))
+                    }
+                    "logout" => {
+                        check_len!(req.url.path, 1);
+                        let token = read_token!(req);
+                        my_try!(self.manager.logout(&token));
+                        r#""ok""#.to_string()
                     }
                     "leave" => {
                         check_len!(req.url.path, 2);
                         let player_id = parse_id!("player", &*req.url.path[1]);
-                        my_try!(self.manager.leave(player_id));
+                        let token = read_token!(req);
+                        my_try!(self.manager.leave(player_id, &token));
                         // Result is a string - but who cares?
                         r#""ok""#.to_string()
                     }
+                    "resume" => {
+                        check_len!(req.url.path, 2);
+                        let player_id = parse_id!("player", &*req.url.path[1]);
+                        let token = read_token!(req);
+                        // Result is a ResumeInfo
+                        try_manager!(self.manager.resume(player_id, &token))
+                    }
+                    "spectate" => {
+                        check_len!(req.url.path, 2);
+                        let party_id = parse_id!("party", &*req.url.path[1]);
+                        // Result is a SpectatorInfo
+                        try_manager!(self.manager.spectate(party_id))
+                    }
                     "pass" => {
                         check_len!(req.url.path, 2);
                         let player_id = parse_id!("player", &*req.url.path[1]);
+                        let token = read_token!(req);
                         // Result is an event
-                        try_manager!(self.manager.pass(player_id))
+                        try_manager!(self.manager.pass(player_id, &token))
                     }
                     "coinche" => {
                         check_len!(req.url.path, 2);
                         let player_id = parse_id!("player", &*req.url.path[1]);
+                        let token = read_token!(req);
                         // Result is an event
-                        try_manager!(self.manager.coinche(player_id))
+                        try_manager!(self.manager.coinche(player_id, &token))
                     }
                     "bid" => {
                         trace!("Request: POST /bid");
                         check_len!(req.url.path, 2);
                         let player_id = parse_id!("player", &*req.url.path[1]);
+                        let token = read_token!(req);
                         trace!("bid from {}", player_id);
                         // Parse the body
 
@@ -306,16 +764,17 @@ impl iron::Handler for Router {
                                                   "contract");
                         trace!("Bidding {:?}", contract);
                         // Result is an event
-                        try_manager!(self.manager.bid(player_id, contract))
+                        try_manager!(self.manager.bid(player_id, &token, contract))
                     }
                     "play" => {
                         check_len!(req.url.path, 2);
                         let player_id = parse_id!("player", &*req.url.path[1]);
+                        let token = read_token!(req);
                         // Parse the body
                         let card = read_body!(req.get::<bodyparser::Struct<CardBody>>(), "card");
 
                         // Result is an event
-                        try_manager!(self.manager.play_card(player_id, card))
+                        try_manager!(self.manager.play_card(player_id, &token, card))
                     }
                     _ => {
                         trace!("Requesting invalid path: POST {:?}", &req.url.path);
@@ -335,22 +794,218 @@ impl iron::Handler for Router {
     }
 }
 
+impl iron::Handler for AdminRouter {
+    fn handle(&self, req: &mut Request) -> IronResult<Response> {
+        trace!("AdminRouter::handle()");
+
+        if req.url.path.is_empty() {
+            panic!("Empty request path should never happen.");
+        }
+
+        let content_type: iron::mime::Mime = "application/json"
+                                                 .parse::<iron::mime::Mime>()
+                                                 .unwrap();
+
+        trace!("Admin request: {:?}", req);
+        if let Some(ref secret) = self.secret {
+            if read_token(req).as_ref() != Some(secret) {
+                return manager_err_resp(&error::Error::BadToken);
+            }
+        }
+
+        let response = match (&req.method, &*req.url.path[0]) {
+            (&iron::method::Get, "status") => {
+                check_len!(req.url.path, 1);
+                // Result is a ServerStatus
+                json::encode(&self.manager.status()).unwrap()
+            }
+            (&iron::method::Get, "parties") => {
+                check_len!(req.url.path, 1);
+                // Result is a list of PartyStatuses
+                json::encode(&self.manager.party_statuses()).unwrap()
+            }
+            (&iron::method::Get, "events") => {
+                check_len!(req.url.path, 2);
+                let party_id = parse_id!("party", &*req.url.path[1]);
+                // Result is a list of TimedEvents
+                try_manager!(self.manager.party_events(party_id))
+            }
+            (&iron::method::Post, "cancel") => {
+                check_len!(req.url.path, 2);
+                let party_id = parse_id!("party", &*req.url.path[1]);
+                let options = match req.get::<bodyparser::Struct<CancelBody>>() {
+                    Ok(Some(options)) => options,
+                    Ok(None) => CancelBody::default(),
+                    Err(err) => return err_resp(format!("Error parsing cancel options: {:?}", err)),
+                };
+                my_try!(self.manager.cancel_party(party_id, options.msg));
+                r#""ok""#.to_string()
+            }
+            (&iron::method::Post, "kick") => {
+                check_len!(req.url.path, 2);
+                let player_id = parse_id!("player", &*req.url.path[1]);
+                my_try!(self.manager.kick(player_id));
+                r#""ok""#.to_string()
+            }
+            _ => {
+                trace!("Requesting invalid admin path: {:?} {:?}",
+                       req.method,
+                       &req.url.path);
+                return admin_help_resp();
+            }
+        };
+
+        Ok(Response::with((content_type, iron::status::Ok, response)))
+    }
+}
+
 impl Server {
-    pub fn new(port: u16) -> Server {
-        Server {
-            port: port,
-            manager: Arc::new(GameManager::new()),
+    /// Creates a server listening on localhost, on the given port.
+    ///
+    /// Fails if the game manager can't load its saved parties.
+    pub fn new(port: u16, settings: Settings) -> Result<Server, String> {
+        let manager = try!(GameManager::new(settings));
+        Ok(Server {
+            addresses: vec![SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::new(127, 0, 0, 1), port))],
+            tls: None,
+            admin_port: None,
+            admin_secret: None,
+            ws_port: None,
+            tcp_port: None,
+            manager: Arc::new(manager),
+        })
+    }
+
+    /// Creates a server from a configuration.
+    ///
+    /// Fails on an invalid configuration, or if saved parties can't be loaded.
+    pub fn from_config(config: &Config) -> Result<Server, String> {
+        let addresses = try!(config.addresses());
+        let tls = match (&config.cert, &config.key) {
+            (&Some(ref cert), &Some(ref key)) => Some((PathBuf::from(cert), PathBuf::from(key))),
+            (&None, &None) => None,
+            _ => return Err("HTTPS needs both a certificate and a key".to_string()),
+        };
+        if tls.is_some() && config.ws_port.is_some() {
+            return Err(NO_WSS.to_string());
+        }
+        if tls.is_some() && config.tcp_port.is_some() {
+            return Err(NO_TCP_TLS.to_string());
         }
+
+        let mut server = try!(Server::new(config.port.unwrap_or(DEFAULT_PORT),
+                                          config.settings()));
+        server.addresses = addresses;
+        server.tls = tls;
+        server.admin_port = config.admin_port;
+        server.admin_secret = config.admin_secret.clone();
+        server.ws_port = config.ws_port;
+        server.tcp_port = config.tcp_port;
+        Ok(server)
+    }
+
+    /// Returns the game manager behind the server.
+    pub fn manager(&self) -> Arc<GameManager> {
+        self.manager.clone()
+    }
+
+    /// Listens on the given addresses instead of localhost.
+    pub fn with_addresses(mut self, addresses: Vec<SocketAddr>) -> Server {
+        self.addresses = addresses;
+        self
+    }
+
+    /// Serves HTTPS instead of HTTP, with the given certificate and private key files.
+    pub fn with_tls(mut self, certificate: PathBuf, key: PathBuf) -> Server {
+        self.tls = Some((certificate, key));
+        self
+    }
+
+    /// Also serves the admin interface on the given port.
+    ///
+    /// Whatever the other addresses, it only listens on localhost, over HTTP.
+    pub fn with_admin(mut self, admin_port: u16) -> Server {
+        self.admin_port = Some(admin_port);
+        self
+    }
+
+    /// Also pushes events over WebSockets, on the given port.
+    ///
+    /// It listens on the same addresses as the main interface, over plain `ws://`:
+    /// it cannot be used with `with_tls`.
+    pub fn with_websocket(mut self, ws_port: u16) -> Server {
+        self.ws_port = Some(ws_port);
+        self
+    }
+
+    /// Also serves the line-based JSON protocol over plain TCP, on the given port.
+    ///
+    /// It listens on the same addresses as the main interface, without TLS:
+    /// it cannot be used with `with_tls`.
+    pub fn with_tcp(mut self, tcp_port: u16) -> Server {
+        self.tcp_port = Some(tcp_port);
+        self
     }
 
     pub fn run(self) {
-        let port = self.port;
-        println!("Listening on port {}", port);
+        if self.tls.is_some() && self.ws_port.is_some() {
+            panic!("{}", NO_WSS);
+        }
+        if self.tls.is_some() && self.tcp_port.is_some() {
+            panic!("{}", NO_TCP_TLS);
+        }
 
-        let router = Router { manager: self.manager.clone() };
+        GameManager::spawn_reaper(self.manager.clone());
+
+        // Keep the admin server alive as long as the main one.
+        let _admin = self.admin_port.map(|admin_port| {
+            println!("Admin interface on port {}", admin_port);
+            let admin = AdminRouter {
+                manager: self.manager.clone(),
+                secret: self.admin_secret.clone(),
+            };
+            Iron::new(admin).http(("localhost", admin_port)).unwrap()
+        });
+
+        if let Some(ws_port) = self.ws_port {
+            for address in &self.addresses {
+                let mut address = *address;
+                address.set_port(ws_port);
+                websocket::spawn(address, self.manager.clone());
+            }
+        }
 
-        Iron::new(router).http(("localhost", port)).unwrap();
+        if let Some(tcp_port) = self.tcp_port {
+            for address in &self.addresses {
+                let mut address = *address;
+                address.set_port(tcp_port);
+                tcp::spawn(address, self.manager.clone());
+            }
+        }
 
+        // Dropping a listener waits for it: keep them all until the end.
+        let mut listeners = Vec::new();
+        for address in &self.addresses {
+            // Each address has its own threads, so its own streams.
+            let iron = Iron::new(Router {
+                manager: self.manager.clone(),
+                streams: Arc::new(AtomicUsize::new(0)),
+            });
+            let listening = match self.tls {
+                Some((ref certificate, ref key)) => {
+                    println!("Listening on https://{}", address);
+                    iron.https(*address, certificate.clone(), key.clone())
+                }
+                None => {
+                    println!("Listening on http://{}", address);
+                    iron.http(*address)
+                }
+            };
 
+            match listening {
+                Ok(listening) => listeners.push(listening),
+                Err(err) => panic!("Could not listen on {}: {}", address, err),
+            }
+        }
     }
-}
\ No newline at end of file
+}
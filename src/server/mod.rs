@@ -1,4 +1,11 @@
 pub mod error;
+pub mod account;
+mod bot;
+mod storage;
 pub mod game_manager;
+pub mod console;
+pub mod config;
+pub mod websocket;
+pub mod tcp;
 
 pub mod http;
@@ -0,0 +1,143 @@
+//! Keeps parties on disk, so they survive a server restart.
+//!
+//! Each party is an append-only file: a header line describing the seats,
+//! followed by one line per event (with its time), all in JSON.
+
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use rustc_serialize::json;
+
+use libcoinche::pos;
+use replay::TimedEvent;
+
+/// A human seat in a stored party.
+#[derive(Clone,Debug,RustcEncodable,RustcDecodable)]
+pub struct SeatRecord {
+    /// Player ID used by the player on this seat.
+    pub player_id: u32,
+    /// Position of the seat.
+    pub pos: pos::PlayerPos,
+    /// Token owning the seat.
+    pub token: String,
+    /// Account the seat is tied to, if any.
+    pub account: Option<String>,
+}
+
+/// What a party looks like before its first event.
+#[derive(Clone,Debug,RustcEncodable,RustcDecodable)]
+pub struct PartyHeader {
+    /// Seats taken by bots.
+    pub bots: [bool; 4],
+    /// Name of each seat.
+    pub names: Vec<String>,
+    /// Seats taken by humans.
+    pub seats: Vec<SeatRecord>,
+}
+
+/// A party read back from the disk.
+pub struct PartyRecord {
+    pub id: u32,
+    pub header: PartyHeader,
+    pub events: Vec<TimedEvent>,
+}
+
+/// Stores parties in a directory.
+pub struct Storage {
+    dir: PathBuf,
+}
+
+impl Storage {
+    /// Opens the given directory, creating it if needed.
+    pub fn open<P: AsRef<Path>>(dir: P) -> io::Result<Self> {
+        try!(fs::create_dir_all(dir.as_ref()));
+        Ok(Storage { dir: dir.as_ref().to_path_buf() })
+    }
+
+    fn path(&self, party_id: u32) -> PathBuf {
+        self.dir.join(format!("{}.party", party_id))
+    }
+
+    /// Starts the file for a new party.
+    pub fn create(&self, party_id: u32, header: &PartyHeader) -> io::Result<()> {
+        let mut file = try!(fs::File::create(self.path(party_id)));
+        writeln!(file, "{}", json::encode(header).unwrap())
+    }
+
+    /// Adds an event at the end of a party file.
+    pub fn append(&self, party_id: u32, event: &TimedEvent) -> io::Result<()> {
+        let mut file = try!(fs::OpenOptions::new().append(true).open(self.path(party_id)));
+        writeln!(file, "{}", json::encode(event).unwrap())
+    }
+
+    /// Forgets about a party.
+    pub fn remove(&self, party_id: u32) -> io::Result<()> {
+        fs::remove_file(self.path(party_id))
+    }
+
+    /// Reads every stored party.
+    ///
+    /// Unreadable files are skipped with a warning.
+    pub fn load(&self) -> io::Result<Vec<PartyRecord>> {
+        let mut result = Vec::new();
+
+        for entry in try!(fs::read_dir(&self.dir)) {
+            let path = try!(entry).path();
+            if path.extension().and_then(|ext| ext.to_str()) != Some("party") {
+                continue;
+            }
+
+            let id = match path.file_stem().and_then(|stem| stem.to_str()) {
+                Some(stem) => {
+                    match u32::from_str(stem) {
+                        Ok(id) => id,
+                        Err(_) => continue,
+                    }
+                }
+                None => continue,
+            };
+
+            match read_party(&path) {
+                Ok((header, events)) => {
+                    result.push(PartyRecord {
+                        id: id,
+                        header: header,
+                        events: events,
+                    })
+                }
+                Err(err) => warn!("Skipping party file {}: {}", path.display(), err),
+            }
+        }
+
+        Ok(result)
+    }
+}
+
+// Reads the header and the events from a party file.
+fn read_party(path: &Path) -> Result<(PartyHeader, Vec<TimedEvent>), String> {
+    let file = try!(fs::File::open(path).map_err(|err| err.to_string()));
+    let mut lines = io::BufReader::new(file).lines();
+
+    let header = match lines.next() {
+        Some(Ok(line)) => try!(json::decode(&line).map_err(|err| err.to_string())),
+        Some(Err(err)) => return Err(err.to_string()),
+        None => return Err("empty file".to_string()),
+    };
+
+    let mut events = Vec::new();
+    for line in lines {
+        let line = try!(line.map_err(|err| err.to_string()));
+        match json::decode(&line) {
+            Ok(event) => events.push(event),
+            Err(err) => {
+                // Probably a write interrupted by a crash: keep what we have.
+                warn!("Truncating party file {}: {}", path.display(), err);
+                break;
+            }
+        }
+    }
+
+    Ok((header, events))
+}
@@ -0,0 +1,287 @@
+//! Plain TCP interface, with one JSON message per line.
+//!
+//! Clients send `TcpRequest`s, tagged with an ID. The server answers each with a
+//! `TcpMessage` carrying the same ID, and pushes the events of the party as
+//! `TcpMessage`s without an ID once the client has a seat.
+
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::thread;
+
+use rustc_serialize::{json, Encodable};
+use num_cpus;
+
+use {TcpRequest, TcpMessage, Event, ActionBody, JoinBody, Error};
+use super::error;
+use super::game_manager::GameManager;
+
+// Seat taken on a connection.
+struct Seat {
+    player_id: u32,
+    token: String,
+    // Tells the event stream to stop.
+    closed: Arc<AtomicBool>,
+}
+
+// Frees a connection slot once the connection is over.
+struct Slot(Arc<AtomicUsize>);
+
+impl Drop for Slot {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+// Maximum number of connections served at once, on each address.
+//
+// Each connection has a thread reading its requests, and another one pushing
+// its events.
+fn max_connections() -> usize {
+    16 * num_cpus::get()
+}
+
+/// Listens for TCP connections, in a new thread.
+///
+/// Past `max_connections`, new connections get an error and are closed.
+pub fn spawn(address: SocketAddr, manager: Arc<GameManager>) -> thread::JoinHandle<()> {
+    let listener = match TcpListener::bind(address) {
+        Ok(listener) => listener,
+        Err(err) => panic!("Could not listen on {}: {}", address, err),
+    };
+    println!("TCP interface on {}", address);
+
+    thread::spawn(move || {
+        let connections = Arc::new(AtomicUsize::new(0));
+        for stream in listener.incoming() {
+            // Only this thread adds connections: nobody can take the slot meanwhile.
+            if connections.load(Ordering::SeqCst) >= max_connections() {
+                if let Ok(stream) = stream {
+                    refuse(stream).ok();
+                }
+                continue;
+            }
+            connections.fetch_add(1, Ordering::SeqCst);
+            let slot = Slot(connections.clone());
+
+            let manager = manager.clone();
+            thread::spawn(move || {
+                let _slot = slot;
+                let result = stream.and_then(|stream| handle(stream, manager));
+                if let Err(err) = result {
+                    trace!("TCP connection closed: {}", err);
+                }
+            });
+        }
+    })
+}
+
+// Tells a client there is no room for it, and closes the connection.
+fn refuse(stream: TcpStream) -> io::Result<()> {
+    trace!("Too many TCP connections: refusing one");
+    let message = TcpMessage {
+        id: None,
+        result: None,
+        error: Some(manager_error(error::Error::TooManyConnections)),
+        event: None,
+    };
+    send(&Mutex::new(stream), &message)
+}
+
+// Serves a single client, until it closes the connection.
+fn handle(stream: TcpStream, manager: Arc<GameManager>) -> io::Result<()> {
+    let writer = Arc::new(Mutex::new(try!(stream.try_clone())));
+    let mut seat: Option<Seat> = None;
+
+    for line in BufReader::new(stream).lines() {
+        let line = try!(line);
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let request: TcpRequest = match json::decode(&line) {
+            Ok(request) => request,
+            Err(err) => {
+                try!(send(&writer,
+                          &TcpMessage {
+                              id: None,
+                              result: None,
+                              error: Some(Error {
+                                  error: format!("Error parsing request: {}", err),
+                                  code: None,
+                              }),
+                              event: None,
+                          }));
+                continue;
+            }
+        };
+
+        let id = request.id;
+        let (message, stream_from) = match run_request(&manager, request, &mut seat) {
+            Ok((result, stream_from)) => {
+                (TcpMessage {
+                    id: Some(id),
+                    result: Some(result),
+                    error: None,
+                    event: None,
+                },
+                 stream_from)
+            }
+            Err(err) => {
+                (TcpMessage {
+                    id: Some(id),
+                    result: None,
+                    error: Some(err),
+                    event: None,
+                },
+                 None)
+            }
+        };
+        try!(send(&writer, &message));
+
+        // Events come after the response that gave the seat.
+        if let (Some(seat), Some(event_id)) = (seat.as_ref(), stream_from) {
+            spawn_stream(manager.clone(), writer.clone(), seat, event_id);
+        }
+    }
+
+    if let Some(seat) = seat {
+        seat.closed.store(true, Ordering::SeqCst);
+    }
+    Ok(())
+}
+
+fn manager_error(err: error::Error) -> Error {
+    Error {
+        error: err.to_string(),
+        code: Some(err.code().to_string()),
+    }
+}
+
+fn to_json<T: Encodable>(value: &T) -> json::Json {
+    json::Json::from_str(&json::encode(value).unwrap()).unwrap()
+}
+
+// Runs a request, and returns its result.
+//
+// After a `join` or a `resume`, also returns the ID of the first event to push.
+fn run_request(manager: &GameManager,
+               request: TcpRequest,
+               seat: &mut Option<Seat>)
+               -> Result<(json::Json, Option<usize>), Error> {
+    let invalid = |msg: &str| {
+        Error {
+            error: msg.to_string(),
+            code: None,
+        }
+    };
+
+    match &*request.action {
+        "join" => {
+            let options = request.join.unwrap_or(JoinBody::default());
+            let login = request.token.as_ref().map(|t| &**t);
+            let info = try!(manager.join(options, login).map_err(manager_error));
+            take_seat(seat, info.player_id, &info.token);
+            return Ok((to_json(&info), Some(0)));
+        }
+        "resume" => {
+            let (player_id, token) = match (request.player_id, request.token) {
+                (Some(player_id), Some(token)) => (player_id, token),
+                _ => return Err(invalid("resume needs a player_id and a token")),
+            };
+            let info = try!(manager.resume(player_id, &token).map_err(manager_error));
+            take_seat(seat, player_id, &token);
+            // Replay the current deal, like the HTTP client does.
+            return Ok((to_json(&info), Some(info.deal_event_id)));
+        }
+        _ => (),
+    }
+
+    let (player_id, token) = match seat.as_ref() {
+        Some(seat) => (seat.player_id, seat.token.clone()),
+        None => return Err(invalid("no seat yet: join or resume first")),
+    };
+
+    if request.action == "leave" {
+        // Stop the events before they fail.
+        if let Some(seat) = seat.take() {
+            seat.closed.store(true, Ordering::SeqCst);
+        }
+        try!(manager.leave(player_id, &token).map_err(manager_error));
+        return Ok((json::Json::String("ok".to_string()), None));
+    }
+
+    let action = ActionBody {
+        action: request.action,
+        target: request.target,
+        suit: request.suit,
+        card: request.card,
+    };
+    // The event is also pushed with the others.
+    manager.run_action(player_id, &token, action)
+           .map(|event| (to_json(&event), None))
+           .map_err(manager_error)
+}
+
+// Takes a new seat, leaving the events of the previous one.
+fn take_seat(seat: &mut Option<Seat>, player_id: u32, token: &str) {
+    if let Some(seat) = seat.take() {
+        seat.closed.store(true, Ordering::SeqCst);
+    }
+    *seat = Some(Seat {
+        player_id: player_id,
+        token: token.to_string(),
+        closed: Arc::new(AtomicBool::new(false)),
+    });
+}
+
+// Pushes every event of the party to the client, in a new thread.
+fn spawn_stream(manager: Arc<GameManager>,
+                writer: Arc<Mutex<TcpStream>>,
+                seat: &Seat,
+                event_id: usize) {
+    let player_id = seat.player_id;
+    let token = seat.token.clone();
+    let closed = seat.closed.clone();
+
+    thread::spawn(move || {
+        let result = manager.push_events(player_id, &token, event_id, |event| {
+            match event {
+                _ if closed.load(Ordering::SeqCst) => false,
+                Some(event) => push(&writer, event).is_ok(),
+                None => true,
+            }
+        });
+
+        // Once the seat is left, the events are expected to fail.
+        if let Err(err) = result {
+            if !closed.load(Ordering::SeqCst) {
+                let message = TcpMessage {
+                    id: None,
+                    result: None,
+                    error: Some(manager_error(err)),
+                    event: None,
+                };
+                send(&writer, &message).ok();
+            }
+        }
+    });
+}
+
+fn push(writer: &Mutex<TcpStream>, event: Event) -> io::Result<()> {
+    send(writer,
+         &TcpMessage {
+             id: None,
+             result: None,
+             error: None,
+             event: Some(event),
+         })
+}
+
+// Writes a message on its own line.
+fn send(writer: &Mutex<TcpStream>, message: &TcpMessage) -> io::Result<()> {
+    let mut writer = writer.lock().unwrap();
+    try!(writeln!(writer, "{}", json::encode(message).unwrap()));
+    writer.flush()
+}
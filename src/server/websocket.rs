@@ -0,0 +1,208 @@
+//! WebSocket interface, pushing events to the players as they happen.
+//!
+//! A client first sends a `ConnectBody` to take its seat. The server then sends
+//! every `Event` of the party, and the client sends its actions as `ActionBody`
+//! messages on the same socket. Errors are sent as `Error` messages.
+
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::thread;
+
+use rustc_serialize::{json, Encodable};
+use num_cpus;
+use websocket;
+use websocket::{Message, Sender as SenderTrait, Receiver as ReceiverTrait};
+use websocket::message::Type;
+use websocket::stream::WebSocketStream;
+
+use {ConnectBody, ActionBody, Error};
+use super::error;
+use super::game_manager::GameManager;
+
+type Sender = websocket::server::Sender<WebSocketStream>;
+type Receiver = websocket::server::Receiver<WebSocketStream>;
+type Connection = websocket::server::Connection<WebSocketStream, WebSocketStream>;
+
+// Counts a connection until it's dropped.
+struct Slot(Arc<AtomicUsize>);
+
+impl Drop for Slot {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+// Maximum number of sockets open at once, on each address.
+//
+// Each socket keeps two threads: one reading actions, one sending events.
+fn max_connections() -> usize {
+    16 * num_cpus::get()
+}
+
+/// Listens for WebSocket connections, in a new thread.
+///
+/// Past `max_connections`, new connections are closed right away.
+pub fn spawn(address: SocketAddr, manager: Arc<GameManager>) -> thread::JoinHandle<()> {
+    let server = match websocket::Server::bind(address) {
+        Ok(server) => server,
+        Err(err) => panic!("Could not listen on {}: {}", address, err),
+    };
+    println!("WebSocket interface on ws://{}", address);
+
+    thread::spawn(move || {
+        let connections = Arc::new(AtomicUsize::new(0));
+        for connection in server {
+            // Sockets are only added here, so the count can't grow meanwhile.
+            if connections.load(Ordering::SeqCst) >= max_connections() {
+                trace!("Too many WebSockets: closing a new connection");
+                continue;
+            }
+            connections.fetch_add(1, Ordering::SeqCst);
+            let slot = Slot(connections.clone());
+
+            let manager = manager.clone();
+            thread::spawn(move || {
+                let _slot = slot;
+                let result = match connection {
+                    Ok(connection) => handle(connection, &manager),
+                    Err(err) => Err(err.to_string()),
+                };
+                if let Err(err) = result {
+                    trace!("WebSocket closed: {}", err);
+                }
+            });
+        }
+    })
+}
+
+// Serves a single player, until he leaves or closes the socket.
+fn handle(connection: Connection, manager: &Arc<GameManager>) -> Result<(), String> {
+    let request = try!(connection.read_request().map_err(|err| err.to_string()));
+    try!(request.validate().map_err(|err| err.to_string()));
+    let client = try!(request.accept().send().map_err(|err| err.to_string()));
+
+    let (sender, mut receiver) = client.split();
+    let sender = Arc::new(Mutex::new(sender));
+
+    let connect: ConnectBody = match try!(read_text(&mut receiver, &sender)) {
+        Some(text) => try!(json::decode(&text).map_err(|err| err.to_string())),
+        None => return Ok(()),
+    };
+    let player_id = connect.player_id;
+    let token = connect.token;
+
+    // Check the token before streaming anything.
+    if let Err(err) = manager.see_pos(player_id, &token) {
+        try!(send_error(&sender, &err));
+        return Err(err.to_string());
+    }
+
+    // Tells the event stream to stop when the socket is closed.
+    let closed = Arc::new(AtomicBool::new(false));
+    {
+        let manager = manager.clone();
+        let sender = sender.clone();
+        let closed = closed.clone();
+        let token = token.clone();
+        thread::spawn(move || {
+            stream_events(&manager, &sender, &closed, player_id, &token, connect.event_id)
+        });
+    }
+
+    let result = read_actions(manager, &mut receiver, &sender, player_id, &token);
+    closed.store(true, Ordering::SeqCst);
+    result
+}
+
+// Runs every action sent by the player.
+fn read_actions(manager: &GameManager,
+                receiver: &mut Receiver,
+                sender: &Mutex<Sender>,
+                player_id: u32,
+                token: &str)
+                -> Result<(), String> {
+    while let Some(text) = try!(read_text(receiver, sender)) {
+        let action: ActionBody = match json::decode(&text) {
+            Ok(action) => action,
+            Err(err) => {
+                try!(send(sender,
+                          &Error {
+                              error: format!("Error parsing action: {}", err),
+                              code: None,
+                          }));
+                continue;
+            }
+        };
+
+        if action.action == "leave" {
+            try!(manager.leave(player_id, token)
+                        .or_else(|err| send_error(sender, &err)));
+            return Ok(());
+        }
+
+        // Events caused by the action are sent by the event stream.
+        if let Err(err) = manager.run_action(player_id, token, action) {
+            try!(send_error(sender, &err));
+        }
+    }
+
+    Ok(())
+}
+
+// Sends every event of the party to the player, as they happen.
+fn stream_events(manager: &GameManager,
+                 sender: &Mutex<Sender>,
+                 closed: &AtomicBool,
+                 player_id: u32,
+                 token: &str,
+                 event_id: usize) {
+    let result = manager.push_events(player_id, token, event_id, |event| {
+        match event {
+            _ if closed.load(Ordering::SeqCst) => false,
+            Some(event) => send(sender, &event).is_ok(),
+            None => true,
+        }
+    });
+
+    if let Err(err) = result {
+        send_error(sender, &err).ok();
+    }
+}
+
+// Reads the next text message.
+// Returns `None` once the client closed the socket.
+fn read_text(receiver: &mut Receiver, sender: &Mutex<Sender>) -> Result<Option<String>, String> {
+    loop {
+        let message: Message = try!(receiver.recv_message().map_err(|err| err.to_string()));
+        match message.opcode {
+            Type::Text => {
+                return String::from_utf8(message.payload.into_owned())
+                           .map(Some)
+                           .map_err(|err| err.to_string())
+            }
+            Type::Ping => {
+                let pong = Message::pong(message.payload);
+                try!(sender.lock().unwrap().send_message(&pong).map_err(|err| err.to_string()));
+            }
+            Type::Close => {
+                sender.lock().unwrap().send_message(&Message::close()).ok();
+                return Ok(None);
+            }
+            _ => (),
+        }
+    }
+}
+
+fn send<T: Encodable>(sender: &Mutex<Sender>, value: &T) -> Result<(), String> {
+    let message = Message::text(json::encode(value).unwrap());
+    sender.lock().unwrap().send_message(&message).map_err(|err| err.to_string())
+}
+
+fn send_error(sender: &Mutex<Sender>, err: &error::Error) -> Result<(), String> {
+    send(sender,
+         &Error {
+             error: err.to_string(),
+             code: Some(err.code().to_string()),
+         })
+}